@@ -0,0 +1,37 @@
+//! Substitution guarantees on arbitrary templates.
+//!
+//! Templates are rendered in a single pass over the original input, so the
+//! output does not depend on the iteration order of variables, nor on how
+//! the template is processed (one-shot, pre-parsed, or streamed).
+
+#![no_main]
+
+use envsubst::Substitutor;
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+
+fuzz_target!(|data: &[u8]| {
+    let template = match std::str::from_utf8(data) {
+        Ok(template) => template,
+        Err(_) => return,
+    };
+
+    // Values which look like placeholders, and would be re-matched by a
+    // naive replace loop.
+    let pairs = [("A", "${B}"), ("B", "$"), ("x", ""), ("é", "${A}")];
+    let to_owned = |(k, v): &(&str, &str)| (k.to_string(), v.to_string());
+    let env: HashMap<_, _> = pairs.iter().map(to_owned).collect();
+    let reordered: HashMap<_, _> = pairs.iter().rev().map(to_owned).collect();
+
+    let out = envsubst::substitute(template, &env).unwrap();
+    assert_eq!(envsubst::substitute(template, &reordered).unwrap(), out);
+    assert_eq!(envsubst::substitute(envsubst::escape(template), &env).unwrap(), template);
+
+    let engine = Substitutor::new();
+    assert_eq!(engine.compile(template).unwrap().render(&env).unwrap(), out);
+    let mut streamed = Vec::new();
+    engine
+        .render_stream(template.as_bytes(), &mut streamed, &env)
+        .unwrap();
+    assert_eq!(streamed, out.as_bytes());
+});
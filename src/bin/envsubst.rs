@@ -0,0 +1,272 @@
+//! Command-line variables substitution, mirroring the GNU `envsubst` utility.
+//!
+//! Both `$VAR` and `${VAR}` placeholders are substituted, and undefined
+//! variables are replaced with an empty string unless `--strict` is given.
+
+use envsubst::env::EnvImport;
+use envsubst::{Error, Escape, IoOperation, Missing, Substitutor, Syntax};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+const USAGE: &str = "\
+Usage: envsubst [OPTIONS] [FILE]
+
+Substitute environment variables in FILE (or standard input), and write
+the result to standard output.
+
+Options:
+  --var KEY=VALUE    Define variable KEY, overriding the environment
+  --strict           Fail on undefined variables, instead of substituting
+                     an empty string
+  --variables        Print the variables referenced by the input, and exit
+  --preview          Print the input with placeholders marked, showing how
+                     they resolve, and exit
+  -o, --output FILE  Write to FILE instead of standard output
+  -h, --help         Print this help, and exit
+  -V, --version      Print version information, and exit
+";
+
+/// Command-line options.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    vars: Vec<(String, String)>,
+    strict: bool,
+    list_variables: bool,
+    preview: bool,
+    input: Option<String>,
+    output: Option<String>,
+}
+
+/// Result of command-line parsing.
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(args) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("envsubst {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(msg) => {
+            eprintln!("envsubst: {}\n\n{}", msg, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(msg) = run(options) {
+        eprintln!("{}", msg);
+        exit(1);
+    }
+}
+
+/// Parse command-line arguments, excluding the program name.
+fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for option '{}'", name))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--strict" => options.strict = true,
+            "--variables" => options.list_variables = true,
+            "--preview" => options.preview = true,
+            "-o" | "--output" => options.output = Some(value(&arg)?),
+            "--var" => {
+                let var = value(&arg)?;
+                let (k, v) = var
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid variable '{}', expected KEY=VALUE", var))?;
+                options.vars.push((k.to_string(), v.to_string()));
+            }
+            "-" if options.input.is_none() => options.input = Some(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if options.input.is_none() => options.input = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+/// Run the substitution, returning an error message on failure.
+fn run(options: Options) -> Result<(), String> {
+    let missing = if options.strict {
+        Missing::Error
+    } else {
+        Missing::Empty
+    };
+    let engine = Substitutor::new()
+        .syntax(Syntax::Dollar)
+        .escape(Escape::None)
+        .missing(missing);
+
+    let input: Box<dyn Read> = match options.input.as_deref() {
+        None | Some("-") => Box::new(io::stdin()),
+        Some(path) => {
+            let file = File::open(path)
+                .map_err(|e| format!("envsubst error: failed to open '{}': {}", path, e))?;
+            Box::new(file)
+        }
+    };
+    let reader = BufReader::new(input);
+
+    let mut vars: HashMap<String, String> = EnvImport::new().collect();
+    vars.extend(options.vars.iter().cloned());
+
+    let path = match options.output.as_deref() {
+        None => return render(&engine, &options, &vars, reader, Box::new(io::stdout())),
+        Some(path) => path,
+    };
+    // Write to a temporary file next to the output, then rename it, so that
+    // the output is complete, and the input intact if it is the same file.
+    let temp = temp_path(Path::new(path));
+    let file = File::create(&temp)
+        .map_err(|e| format!("envsubst error: failed to create '{}': {}", path, e))?;
+    if let Ok(metadata) = fs::metadata(path) {
+        let _ = fs::set_permissions(&temp, metadata.permissions());
+    }
+    let renamed = render(&engine, &options, &vars, reader, Box::new(file)).and_then(|_| {
+        fs::rename(&temp, path)
+            .map_err(|e| format!("envsubst error: failed to create '{}': {}", path, e))
+    });
+    if renamed.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    renamed
+}
+
+/// Render `reader` to `output` as requested by `options`.
+fn render(
+    engine: &Substitutor,
+    options: &Options,
+    vars: &HashMap<String, String>,
+    mut reader: BufReader<Box<dyn Read>>,
+    output: Box<dyn Write>,
+) -> Result<(), String> {
+    let mut writer = BufWriter::new(output);
+    if options.list_variables || options.preview {
+        let mut template = String::new();
+        reader
+            .read_to_string(&mut template)
+            .map_err(|e| format!("envsubst error: failed to read input: {}", e))?;
+        let template = engine.compile(&template).map_err(|e| e.to_string())?;
+        let written = if options.list_variables {
+            template
+                .variables()
+                .try_for_each(|name| writeln!(writer, "{}", name))
+        } else {
+            write!(writer, "{}", template.preview(vars).marked())
+        };
+        return written
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("envsubst error: failed to write output: {}", e));
+    }
+
+    engine
+        .render_stream(reader, writer, vars)
+        .map_err(|e| match (e, options.output.as_deref()) {
+            (
+                Error::Io {
+                    operation: IoOperation::Write,
+                    path: None,
+                    source,
+                },
+                Some(path),
+            ) => Error::Io {
+                operation: IoOperation::Write,
+                path: Some(path.into()),
+                source,
+            },
+            (e, _) => e,
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Temporary file to write `output` to, in the same directory.
+fn temp_path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_else(|| "output".as_ref());
+    let mut temp = OsString::from(".");
+    temp.push(name);
+    temp.push(format!(".{}.tmp", std::process::id()));
+    output.with_file_name(temp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn cli_args() {
+        assert_eq!(parse(&[]), Ok(Command::Run(Options::default())));
+        assert_eq!(parse(&["--strict", "-h"]), Ok(Command::Help));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+
+        let expected = Options {
+            vars: vec![
+                ("A".to_string(), "1=2".to_string()),
+                ("B".to_string(), String::new()),
+            ],
+            strict: true,
+            list_variables: false,
+            preview: false,
+            input: Some("in.tmpl".to_string()),
+            output: Some("out".to_string()),
+        };
+        let args = [
+            "--var", "A=1=2", "in.tmpl", "--strict", "--var", "B=", "-o", "out",
+        ];
+        assert_eq!(parse(&args), Ok(Command::Run(expected)));
+
+        let expected = Options {
+            preview: true,
+            ..Options::default()
+        };
+        assert_eq!(parse(&["--preview"]), Ok(Command::Run(expected)));
+
+        parse(&["--var", "A"]).unwrap_err();
+        parse(&["--output"]).unwrap_err();
+        parse(&["--unknown"]).unwrap_err();
+        parse(&["a", "b"]).unwrap_err();
+    }
+
+    #[test]
+    fn same_input_output() {
+        let path = std::env::temp_dir().join(format!("envsubst-{}.tmpl", std::process::id()));
+        fs::write(&path, "Hello ${NAME}!\n").unwrap();
+        let path_str = path.to_str().unwrap().to_string();
+        let options = Options {
+            vars: vec![("NAME".to_string(), "world".to_string())],
+            input: Some(path_str.clone()),
+            output: Some(path_str),
+            ..Options::default()
+        };
+        run(options).unwrap();
+        let output = fs::read_to_string(&path).unwrap();
+        assert!(!temp_path(&path).exists());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(output, "Hello world!\n");
+    }
+}
@@ -0,0 +1,727 @@
+//! Render bundles, to reproduce renders elsewhere.
+//!
+//! A bundle captures everything a render depends on: the template, the
+//! values of the variables it references, and the substitutor settings. It
+//! can be saved as JSON, e.g. when a render fails or gives a wrong output,
+//! and replayed later without access to the original environment. Values
+//! can be redacted before the bundle leaves the host.
+//!
+//! ```rust
+//! use envsubst::bundle::RenderBundle;
+//! use envsubst::{Missing, Substitutor};
+//!
+//! let mut context = std::collections::HashMap::new();
+//! context.insert("user".to_string(), "admin".to_string());
+//! context.insert("password".to_string(), "hunter2".to_string());
+//!
+//! let engine = Substitutor::new().missing(Missing::Error);
+//! let template = "${user}:${password}@${host}";
+//! if engine.render(template, &context).is_err() {
+//!     let bundle = RenderBundle::capture(&engine, template, &context).redact(["password"]);
+//!     let json = bundle.to_json();
+//!
+//!     // Later, e.g. from a bug report.
+//!     let bundle = RenderBundle::from_json(&json).unwrap();
+//!     assert_eq!(bundle.variables()["password"], "***");
+//!     assert!(bundle.replay().is_err());
+//! }
+//! ```
+
+use crate::placeholder::{Escape, Syntax};
+use crate::report::json_string;
+use crate::scrub::MASK;
+use crate::template::{allowed_entries, referenced_names};
+use crate::{CaseFolding, Error, Missing, Substitutor};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Template, variables and settings of a render.
+///
+/// Custom filters and callbacks cannot be captured: bundles only record
+/// whether filters are enabled, and replay them with the built-in ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderBundle {
+    template: String,
+    variables: BTreeMap<String, String>,
+    redacted: BTreeSet<String>,
+    options: Options,
+}
+
+/// Substitutor settings recorded in a bundle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Options {
+    syntax: Syntax,
+    missing: Missing,
+    escape: Escape,
+    validate_values: bool,
+    max_placeholders: Option<usize>,
+    max_name_len: Option<usize>,
+    max_depth: Option<usize>,
+    filters: bool,
+    case_folding: CaseFolding,
+}
+
+impl RenderBundle {
+    /// Version of the format produced by [`to_json`](Self::to_json).
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Capture the render of `template` with `substitutor` and `variables`.
+    ///
+    /// Only the variables referenced by the template are kept, including
+    /// nested ones in recursive mode, and defaults of the substitutor are
+    /// merged into them. This works for templates which fail to compile too.
+    pub fn capture<T>(
+        substitutor: &Substitutor,
+        template: T,
+        variables: &HashMap<String, String>,
+    ) -> Self
+    where
+        T: Into<String>,
+    {
+        let template = template.into();
+        let mut merged = substitutor
+            .defaults
+            .as_ref()
+            .map_or_else(HashMap::new, |defaults| (**defaults).clone());
+        merged.extend(variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let names = referenced_names(substitutor, &template);
+        let variables = allowed_entries(substitutor, names, &merged);
+
+        Self {
+            template,
+            variables: variables.into_iter().collect(),
+            redacted: BTreeSet::new(),
+            options: Options {
+                syntax: substitutor.syntax,
+                missing: substitutor.missing,
+                escape: substitutor.escape,
+                validate_values: substitutor.validate_values,
+                max_placeholders: substitutor.max_placeholders,
+                max_name_len: substitutor.max_name_len,
+                max_depth: substitutor.max_depth,
+                filters: substitutor.filters.is_some(),
+                case_folding: substitutor.case_folding,
+            },
+        }
+    }
+
+    /// Replace the values of variables `names` with `***`.
+    ///
+    /// Redacted names are recorded in the bundle, as replays then render
+    /// the mask instead of the original values.
+    pub fn redact<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for name in names {
+            if let Some(value) = self.variables.get_mut(name.as_ref()) {
+                *value = MASK.to_string();
+                self.redacted.insert(name.as_ref().to_string());
+            }
+        }
+        self
+    }
+
+    /// Captured template.
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Captured variables, sorted by name.
+    pub fn variables(&self) -> &BTreeMap<String, String> {
+        &self.variables
+    }
+
+    /// Names of the redacted variables.
+    pub fn redacted(&self) -> &BTreeSet<String> {
+        &self.redacted
+    }
+
+    /// Substitutor with the captured settings.
+    pub fn substitutor(&self) -> Substitutor {
+        let options = &self.options;
+        let mut substitutor = Substitutor::new()
+            .syntax(options.syntax)
+            .missing(options.missing)
+            .escape(options.escape)
+            .validate_values(options.validate_values)
+            .filters(options.filters)
+            .case_folding(options.case_folding);
+        substitutor.max_placeholders = options.max_placeholders;
+        substitutor.max_name_len = options.max_name_len;
+        substitutor.max_depth = options.max_depth;
+        substitutor
+    }
+
+    /// Render the captured template again, with the captured variables and
+    /// settings.
+    pub fn replay(&self) -> Result<String, Error> {
+        let variables: HashMap<String, String> = self
+            .variables
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        self.substitutor()
+            .render(self.template.as_str(), &variables)
+    }
+
+    /// Serialize this bundle as a JSON object.
+    ///
+    /// The format is versioned like
+    /// [`SubstitutionReport::to_json`](crate::SubstitutionReport::to_json).
+    /// Version 1 has these fields:
+    ///
+    ///  * `version`: format version, as a number.
+    ///  * `template`: template, as a string.
+    ///  * `variables`: variables, as an object of strings sorted by name.
+    ///  * `redacted`: names of redacted variables, as a sorted array.
+    ///  * `options`: substitutor settings, as an object with `syntax`
+    ///    (`braced`, `dollar` or `double_braces`), `missing` (`keep`,
+    ///    `empty` or `error`), `escape` (`none`, `double_dollar` or
+    ///    `backslash`), `validate_values` and `filters` as booleans,
+    ///    `max_placeholders`, `max_name_len` and `max_depth` as numbers or
+    ///    `null`, and `case_folding` (`unicode` or `ascii`).
+    pub fn to_json(&self) -> String {
+        let variables: Vec<_> = self
+            .variables
+            .iter()
+            .map(|(k, v)| format!("{}:{}", json_string(k), json_string(v)))
+            .collect();
+        let redacted: Vec<_> = self.redacted.iter().map(|k| json_string(k)).collect();
+        let limit = |limit: Option<usize>| limit.map_or("null".to_string(), |l| l.to_string());
+        let options = &self.options;
+        let options = format!(
+            r#"{{"syntax":"{}","missing":"{}","escape":"{}","validate_values":{},"max_placeholders":{},"max_name_len":{},"max_depth":{},"filters":{},"case_folding":"{}"}}"#,
+            syntax_name(options.syntax),
+            missing_name(options.missing),
+            escape_name(options.escape),
+            options.validate_values,
+            limit(options.max_placeholders),
+            limit(options.max_name_len),
+            limit(options.max_depth),
+            options.filters,
+            case_folding_name(options.case_folding),
+        );
+        format!(
+            r#"{{"version":{},"template":{},"variables":{{{}}},"redacted":[{}],"options":{}}}"#,
+            Self::FORMAT_VERSION,
+            json_string(&self.template),
+            variables.join(","),
+            redacted.join(","),
+            options,
+        )
+    }
+
+    /// Parse a bundle serialized by [`to_json`](Self::to_json).
+    ///
+    /// Unknown fields are ignored, and missing settings take their default
+    /// value. Bundles of another format version are rejected.
+    pub fn from_json(input: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            input,
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < input.len() {
+            return Err(invalid("trailing characters after the bundle"));
+        }
+
+        let object = value.object("bundle")?;
+        match field(object, "version") {
+            Some(Json::Number(version)) if *version == Self::FORMAT_VERSION.to_string() => {}
+            _ => return Err(invalid("unsupported format version")),
+        }
+        let template = required(object, "template")?
+            .string("template")?
+            .to_string();
+        let variables = required(object, "variables")?
+            .object("variables")?
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), v.string("variable value")?.to_string())))
+            .collect::<Result<_, Error>>()?;
+        let redacted = match field(object, "redacted") {
+            Some(json) => json
+                .array("redacted")?
+                .iter()
+                .map(|name| name.string("redacted name").map(str::to_string))
+                .collect::<Result<_, Error>>()?,
+            None => BTreeSet::new(),
+        };
+
+        let defaults = Substitutor::new();
+        let options = match field(object, "options") {
+            Some(json) => json.object("options")?,
+            None => &[],
+        };
+        let name = |key: &str| -> Result<Option<&str>, Error> {
+            field(options, key).map(|json| json.string(key)).transpose()
+        };
+        let flag = |key: &str, default: bool| match field(options, key) {
+            Some(Json::Bool(value)) => Ok(*value),
+            Some(_) => Err(invalid(&format!("'{}' is not a boolean", key))),
+            None => Ok(default),
+        };
+        let limit = |key: &str| match field(options, key) {
+            Some(Json::Number(n)) => n
+                .parse()
+                .map(Some)
+                .map_err(|_| invalid(&format!("'{}' is not a valid limit", key))),
+            Some(Json::Null) | None => Ok(None),
+            Some(_) => Err(invalid(&format!("'{}' is not a number", key))),
+        };
+        let unknown = |key: &str| invalid(&format!("unknown '{}' setting", key));
+        let options = Options {
+            syntax: match name("syntax")? {
+                Some("braced") => Syntax::Braced,
+                Some("dollar") => Syntax::Dollar,
+                Some("double_braces") => Syntax::DoubleBraces,
+                Some(_) => return Err(unknown("syntax")),
+                None => defaults.syntax,
+            },
+            missing: match name("missing")? {
+                Some("keep") => Missing::Keep,
+                Some("empty") => Missing::Empty,
+                Some("error") => Missing::Error,
+                Some(_) => return Err(unknown("missing")),
+                None => defaults.missing,
+            },
+            escape: match name("escape")? {
+                Some("none") => Escape::None,
+                Some("double_dollar") => Escape::DoubleDollar,
+                Some("backslash") => Escape::Backslash,
+                Some(_) => return Err(unknown("escape")),
+                None => defaults.escape,
+            },
+            validate_values: flag("validate_values", defaults.validate_values)?,
+            max_placeholders: limit("max_placeholders")?,
+            max_name_len: limit("max_name_len")?,
+            max_depth: limit("max_depth")?,
+            filters: flag("filters", false)?,
+            case_folding: match name("case_folding")? {
+                Some("unicode") => CaseFolding::Unicode,
+                Some("ascii") => CaseFolding::Ascii,
+                Some(_) => return Err(unknown("case_folding")),
+                None => defaults.case_folding,
+            },
+        };
+
+        Ok(Self {
+            template,
+            variables,
+            redacted,
+            options,
+        })
+    }
+}
+
+fn syntax_name(syntax: Syntax) -> &'static str {
+    match syntax {
+        Syntax::Braced => "braced",
+        Syntax::Dollar => "dollar",
+        Syntax::DoubleBraces => "double_braces",
+    }
+}
+
+fn missing_name(missing: Missing) -> &'static str {
+    match missing {
+        Missing::Keep => "keep",
+        Missing::Empty => "empty",
+        Missing::Error => "error",
+    }
+}
+
+fn escape_name(escape: Escape) -> &'static str {
+    match escape {
+        Escape::None => "none",
+        Escape::DoubleDollar => "double_dollar",
+        Escape::Backslash => "backslash",
+    }
+}
+
+fn case_folding_name(case_folding: CaseFolding) -> &'static str {
+    match case_folding {
+        CaseFolding::Unicode => "unicode",
+        CaseFolding::Ascii => "ascii",
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidBundle {
+        reason: reason.to_string(),
+    }
+}
+
+/// A parsed JSON value.
+///
+/// Numbers are kept as text, as bundles only have integers, and objects as
+/// fields in order.
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn string(&self, what: &str) -> Result<&str, Error> {
+        match self {
+            Json::String(value) => Ok(value),
+            _ => Err(invalid(&format!("{} is not a string", what))),
+        }
+    }
+
+    fn array(&self, what: &str) -> Result<&[Json], Error> {
+        match self {
+            Json::Array(values) => Ok(values),
+            _ => Err(invalid(&format!("{} is not an array", what))),
+        }
+    }
+
+    fn object(&self, what: &str) -> Result<&[(String, Json)], Error> {
+        match self {
+            Json::Object(fields) => Ok(fields),
+            _ => Err(invalid(&format!("{} is not an object", what))),
+        }
+    }
+}
+
+/// Last value of field `key` in `object`.
+fn field<'a>(object: &'a [(String, Json)], key: &str) -> Option<&'a Json> {
+    object.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+fn required<'a>(object: &'a [(String, Json)], key: &str) -> Result<&'a Json, Error> {
+    field(object, key).ok_or_else(|| invalid(&format!("missing '{}' field", key)))
+}
+
+/// Code unit of a `\\u` escape, from its 4 hexadecimal digits.
+fn hex_unit(chars: &mut std::str::CharIndices<'_>) -> Option<u32> {
+    let hex: String = chars.take(4).map(|(_, c)| c).collect();
+    u32::from_str_radix(&hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 4)
+}
+
+/// Minimal JSON parser, for reading bundles back.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+/// Maximum nesting of arrays and objects, to bound recursion on untrusted
+/// bundles.
+const MAX_DEPTH: usize = 128;
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Json, Error> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        match rest.bytes().next() {
+            Some(b'{') | Some(b'[') if self.depth >= MAX_DEPTH => {
+                Err(self.error("too deeply nested"))
+            }
+            Some(b'{') | Some(b'[') => {
+                self.depth += 1;
+                let value = match rest.as_bytes()[0] {
+                    b'{' => self.object(),
+                    _ => self.array(),
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ if rest.starts_with("null") => {
+                self.pos += 4;
+                Ok(Json::Null)
+            }
+            _ if rest.starts_with("true") => {
+                self.pos += 4;
+                Ok(Json::Bool(true))
+            }
+            _ if rest.starts_with("false") => {
+                self.pos += 5;
+                Ok(Json::Bool(false))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, Error> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if !self.input[self.pos..].starts_with('"') {
+                return Err(self.error("expected a field name"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Ok(Json::Object(fields));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, Error> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Ok(Json::Array(values));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut value = String::new();
+        let mut chars = self.input[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let code = match hex_unit(&mut chars) {
+                                Some(high @ 0xd800..=0xdbff) => {
+                                    let low = match (chars.next(), chars.next()) {
+                                        (Some((_, '\\')), Some((_, 'u'))) => hex_unit(&mut chars),
+                                        _ => None,
+                                    };
+                                    low.filter(|low| (0xdc00..=0xdfff).contains(low))
+                                        .map(|low| {
+                                            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                                        })
+                                }
+                                code => code,
+                            };
+                            match code.and_then(std::char::from_u32) {
+                                Some(c) => c,
+                                None => return Err(self.error("invalid unicode escape")),
+                            }
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    value.push(escaped);
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => value.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn number(&mut self) -> Result<Json, Error> {
+        let rest = &self.input.as_bytes()[self.pos..];
+        let digits = |from: usize| {
+            rest[from..]
+                .iter()
+                .position(|b| !b.is_ascii_digit())
+                .unwrap_or(rest.len() - from)
+        };
+        let mut len = usize::from(rest[0] == b'-');
+        let integer = digits(len);
+        if integer == 0 || (integer > 1 && rest[len] == b'0') {
+            return Err(self.error("invalid number"));
+        }
+        len += integer;
+        if rest.get(len) == Some(&b'.') {
+            match digits(len + 1) {
+                0 => return Err(self.error("invalid number")),
+                fraction => len += 1 + fraction,
+            }
+        }
+        if matches!(rest.get(len), Some(b'e') | Some(b'E')) {
+            let sign = usize::from(matches!(rest.get(len + 1), Some(b'+') | Some(b'-')));
+            match digits(len + 1 + sign) {
+                0 => return Err(self.error("invalid number")),
+                exponent => len += 1 + sign + exponent,
+            }
+        }
+        let number = self.input[self.pos..self.pos + len].to_string();
+        self.pos += len;
+        Ok(Json::Number(number))
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.input.as_bytes().get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches(&[' ', '\t', '\n', '\r'][..]).len();
+    }
+
+    fn error(&self, reason: &str) -> Error {
+        invalid(&format!("{} at offset {}", reason, self.pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundle_roundtrip() {
+        let mut context = HashMap::new();
+        context.insert("url".to_string(), "${scheme}://${host}".to_string());
+        context.insert("scheme".to_string(), "https".to_string());
+        context.insert("host".to_string(), "Example.com".to_string());
+        context.insert("token".to_string(), "s3cr\"t\n".to_string());
+        context.insert("unused".to_string(), "x".to_string());
+        let mut defaults = HashMap::new();
+        defaults.insert("port".to_string(), "443".to_string());
+
+        let engine = Substitutor::new()
+            .recursive(2)
+            .filters(true)
+            .defaults(defaults)
+            .max_name_len(64)
+            .missing(Missing::Error);
+        let template = "${url|lower}:${port}/?t=${token} é ${missing:-none}";
+        let bundle = RenderBundle::capture(&engine, template, &context);
+        let names: Vec<_> = bundle.variables().keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["host", "port", "scheme", "token", "url"]);
+        assert_eq!(
+            bundle.replay().unwrap(),
+            engine.render(template, &context).unwrap()
+        );
+
+        let json = bundle.to_json();
+        assert!(json.starts_with(r#"{"version":1,"template":"${url|lower}"#));
+        let parsed = RenderBundle::from_json(&json).unwrap();
+        assert_eq!(parsed, bundle);
+
+        let redacted = bundle.redact(vec!["token", "other"]);
+        assert_eq!(
+            redacted.redacted().iter().collect::<Vec<_>>(),
+            vec!["token"]
+        );
+        let parsed = RenderBundle::from_json(&redacted.to_json()).unwrap();
+        assert_eq!(parsed, redacted);
+        assert_eq!(
+            parsed.replay().unwrap(),
+            "https://example.com:443/?t=*** é none"
+        );
+
+        // Templates which do not compile are captured too.
+        let engine = Substitutor::new()
+            .missing(Missing::Error)
+            .max_placeholders(1);
+        let bundle = RenderBundle::capture(&engine, "${host}${host}", &context);
+        let parsed = RenderBundle::from_json(&bundle.to_json()).unwrap();
+        assert!(matches!(
+            parsed.replay().unwrap_err(),
+            Error::TooManyPlaceholders { limit: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn bundle_parsing() {
+        let json = r#" { "version" : 1, "template": "é😀 ${a}\/",
+            "variables": {"a": "1"}, "extra": [null, true, -1.5e3, {}],
+            "options": {"syntax": "double_braces", "max_depth": null} } "#;
+        let bundle = RenderBundle::from_json(json).unwrap();
+        assert_eq!(bundle.template(), "é😀 ${a}/");
+        assert_eq!(bundle.substitutor().syntax, Syntax::DoubleBraces);
+        assert_eq!(bundle.replay().unwrap(), "é😀 ${a}/");
+
+        let nested = "[".repeat(200_000);
+        match RenderBundle::from_json(&nested).unwrap_err() {
+            Error::InvalidBundle { reason } => {
+                assert_eq!(reason, "too deeply nested at offset 128")
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        let err = RenderBundle::from_json(&nested).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: invalid render bundle: bundle is not an object"
+        );
+
+        for (json, reason) in &[
+            ("", "expected a value at offset 0"),
+            ("[]", "bundle is not an object"),
+            (r#"{"version":2}"#, "unsupported format version"),
+            (r#"{"version":1}"#, "missing 'template' field"),
+            (
+                r#"{"version":1,"template":"","variables":{}} x"#,
+                "trailing characters after the bundle",
+            ),
+            (
+                r#"{"version":1,"template":"\x","variables":{}}"#,
+                "invalid escape sequence at offset 25",
+            ),
+            (
+                r#"{"version":1,"template":"","variables":{"a":1}}"#,
+                "variable value is not a string",
+            ),
+            (
+                r#"{"version":1,"template":"","variables":{},"options":{"missing":"ignore"}}"#,
+                "unknown 'missing' setting",
+            ),
+            (
+                r#"{"version":1,"template":"","variables":{},"options":{"max_depth":-1}}"#,
+                "'max_depth' is not a valid limit",
+            ),
+            (
+                r#"{"version":1,"template":"unterminated"#,
+                "unterminated string at offset 25",
+            ),
+            (r#"{"version":-}"#, "invalid number at offset 11"),
+            (r#"{"version":-x}"#, "invalid number at offset 11"),
+            (r#"{"version":01}"#, "invalid number at offset 11"),
+            (r#"{"version":1.}"#, "invalid number at offset 11"),
+            (r#"{"version":1e+}"#, "invalid number at offset 11"),
+        ] {
+            match RenderBundle::from_json(json).unwrap_err() {
+                Error::InvalidBundle { reason: r } => assert_eq!(&r, reason, "{}", json),
+                err => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+}
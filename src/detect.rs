@@ -0,0 +1,200 @@
+//! Placeholder syntax detection.
+
+use crate::Syntax;
+use std::cmp::Reverse;
+
+/// A placeholder notation which can be found in templates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Notation {
+    /// Braced variables, e.g. `${foo}`.
+    Braced,
+    /// Bare variables, e.g. `$foo`.
+    Unbraced,
+    /// Percent-delimited variables, e.g. `%foo%` as in Windows scripts.
+    Percent,
+    /// Double-braces-delimited variables, e.g. `{{ foo }}`.
+    DoubleBraces,
+    /// Parenthesized variables, e.g. `$(foo)` as in Makefiles.
+    Parenthesized,
+}
+
+impl Notation {
+    /// Syntax to render this notation with, if supported.
+    ///
+    /// Templates with both braced and bare variables should be rendered with
+    /// [`Syntax::Dollar`], which supports both.
+    pub fn syntax(&self) -> Option<Syntax> {
+        match self {
+            Notation::Braced => Some(Syntax::Braced),
+            Notation::Unbraced => Some(Syntax::Dollar),
+            Notation::DoubleBraces => Some(Syntax::DoubleBraces),
+            Notation::Percent | Notation::Parenthesized => None,
+        }
+    }
+}
+
+/// A notation found in a template, see [`detect_syntax`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SyntaxGuess {
+    /// Placeholder notation.
+    pub notation: Notation,
+    /// Number of placeholders in this notation.
+    pub count: usize,
+}
+
+/// Detect which placeholder notations appear in `template`, and how often.
+///
+/// Only notations which appear are reported, the most frequent first. This
+/// is a heuristic meant to choose or suggest settings for imported
+/// templates: placeholders must have identifier-like names to count, and
+/// escaped `$$` dollar signs are skipped.
+///
+/// ```rust
+/// use envsubst::Notation;
+///
+/// let guesses = envsubst::detect_syntax("{{ name }} at {{ host }}, ${ENV}");
+/// assert_eq!(guesses[0].notation, Notation::DoubleBraces);
+/// assert_eq!(guesses[0].count, 2);
+/// assert_eq!(guesses[1].notation, Notation::Braced);
+/// assert_eq!(guesses.len(), 2);
+/// ```
+pub fn detect_syntax(template: &str) -> Vec<SyntaxGuess> {
+    let notations = [
+        Notation::Braced,
+        Notation::Unbraced,
+        Notation::Percent,
+        Notation::DoubleBraces,
+        Notation::Parenthesized,
+    ];
+    let mut counts = [0; 5];
+    let bytes = template.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        // Only slice at ASCII trigger bytes, which are always char boundaries.
+        if !matches!(bytes[i], b'$' | b'{' | b'%') {
+            i += 1;
+            continue;
+        }
+        let rest = &template[i..];
+        let found = match bytes[i] {
+            b'$' if rest.starts_with("$$") => Some((None, 2)),
+            b'$' if rest.starts_with("${") => {
+                delimited(&rest[2..], '}', is_name).map(|len| (Some(Notation::Braced), len + 3))
+            }
+            b'$' if rest.starts_with("$(") => delimited(&rest[2..], ')', is_identifier)
+                .map(|len| (Some(Notation::Parenthesized), len + 3)),
+            b'$' => match identifier_len(&rest[1..]) {
+                0 => None,
+                len => Some((Some(Notation::Unbraced), len + 1)),
+            },
+            b'{' if rest.starts_with("{{") => rest[2..]
+                .find("}}")
+                .filter(|end| is_name(&rest[2..2 + end]))
+                .map(|end| (Some(Notation::DoubleBraces), end + 4)),
+            b'%' => delimited(&rest[1..], '%', is_identifier)
+                .map(|len| (Some(Notation::Percent), len + 2)),
+            _ => None,
+        };
+        match found {
+            Some((notation, len)) => {
+                if let Some(notation) = notation {
+                    counts[notation as usize] += 1;
+                }
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+
+    let mut guesses: Vec<_> = notations
+        .iter()
+        .zip(counts.iter())
+        .filter(|(_, count)| **count > 0)
+        .map(|(notation, count)| SyntaxGuess {
+            notation: *notation,
+            count: *count,
+        })
+        .collect();
+    // Stable sort, keeping ties in declaration order.
+    guesses.sort_by_key(|guess| Reverse(guess.count));
+    guesses
+}
+
+/// Length of `input` up to the first `close` character, if the text before
+/// it passes `valid`.
+fn delimited<F>(input: &str, close: char, valid: F) -> Option<usize>
+where
+    F: Fn(&str) -> bool,
+{
+    input.find(close).filter(|end| valid(&input[..*end]))
+}
+
+/// Whether `name` looks like a variable name, possibly with `:-` defaults,
+/// `:?` messages or filters after it.
+fn is_name(name: &str) -> bool {
+    let name = name.trim_start();
+    let rest = &name[identifier_len(name)..];
+    rest.len() < name.len()
+        && !rest.contains('{')
+        && (rest.trim().is_empty() || rest.starts_with(':') || rest.trim_start().starts_with('|'))
+}
+
+/// Whether `name` is a plain identifier.
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && identifier_len(name) == name.len()
+}
+
+/// Length of the identifier at the start of `input`, possibly with dots.
+fn identifier_len(input: &str) -> usize {
+    match input.bytes().next() {
+        Some(b) if b == b'_' || b.is_ascii_alphabetic() => input
+            .bytes()
+            .position(|b| !(b == b'_' || b == b'.' || b.is_ascii_alphanumeric()))
+            .unwrap_or(input.len()),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detected_notations() {
+        let counts = |template: &str| -> Vec<(Notation, usize)> {
+            detect_syntax(template)
+                .into_iter()
+                .map(|guess| (guess.notation, guess.count))
+                .collect()
+        };
+
+        assert_eq!(counts("no placeholders, 50% off"), vec![]);
+        assert_eq!(
+            counts("${A} ${B:-b} $C $$D $${E} ${F|lower}"),
+            vec![(Notation::Braced, 3), (Notation::Unbraced, 1)]
+        );
+        assert_eq!(
+            counts("%APPDATA%\\app %HOME% $(CC) $(shell ls -l) 5% 10%"),
+            vec![(Notation::Percent, 2), (Notation::Parenthesized, 1)]
+        );
+        assert_eq!(
+            counts("{{ name }}{{other}} {{ }} {{ a b }} ${ x }"),
+            vec![(Notation::DoubleBraces, 2), (Notation::Braced, 1)]
+        );
+        assert_eq!(
+            counts("café ${A} → $B, %ÉTÉ% {{ né }} $(Ü) ${é}"),
+            vec![(Notation::Braced, 1), (Notation::Unbraced, 1)]
+        );
+        assert_eq!(
+            counts("→%A%é{{ b }}€$(C)ü"),
+            vec![
+                (Notation::Percent, 1),
+                (Notation::DoubleBraces, 1),
+                (Notation::Parenthesized, 1)
+            ]
+        );
+        assert_eq!(Notation::Unbraced.syntax(), Some(Syntax::Dollar));
+        assert_eq!(Notation::Percent.syntax(), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! Variables from the process environment.
+//!
+//! This captures environment variables into a map for substitution, with
+//! the filtering that is usually needed for it: restricting to a set of
+//! names or to a prefix, and dropping variables which could not be used in
+//! placeholders anyway.
+//!
+//! ```rust
+//! use envsubst::env::EnvImport;
+//!
+//! // Variables starting with `APP_`, e.g. `APP_HOST` as `${HOST}`.
+//! let vars = EnvImport::new().prefix("APP_").strip_prefix(true).collect();
+//! let out = envsubst::substitute("${HOST:-localhost}", &vars).unwrap();
+//!
+//! // Nested names, e.g. `APP_DB__HOST` as `${db.host}`.
+//! let vars = EnvImport::new()
+//!     .prefix("APP_")
+//!     .strip_prefix(true)
+//!     .separator("__", ".")
+//!     .lowercase(true)
+//!     .collect();
+//! ```
+
+use crate::{validate_key, Error};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Capture all usable environment variables.
+///
+/// This is a shortcut for `EnvImport::new().collect()`.
+pub fn vars_from_env() -> HashMap<String, String> {
+    EnvImport::new().collect()
+}
+
+/// Generate a POSIX shell script exporting `variables`.
+///
+/// Each variable gets an `export NAME='value'` line, sorted by name, with
+/// values quoted so that they are taken verbatim by the shell. Names which
+/// are not valid shell identifiers are rejected with [`Error::InvalidKey`],
+/// and empty names are skipped.
+/// To export only the variables used by a template, see
+/// [`Template::shell_exports`](crate::Template::shell_exports).
+///
+/// ```rust
+/// let mut vars = std::collections::HashMap::new();
+/// vars.insert("GREETING".to_string(), "it's me".to_string());
+///
+/// let script = envsubst::env::shell_exports(&vars).unwrap();
+/// assert_eq!(script, "export GREETING='it'\\''s me'\n");
+/// ```
+pub fn shell_exports(variables: &HashMap<String, String>) -> Result<String, Error> {
+    let sorted: BTreeMap<_, _> = variables.iter().collect();
+    let mut script = String::new();
+    for (name, value) in sorted.into_iter().filter(|(name, _)| !name.is_empty()) {
+        if let Some(character) = shell_forbidden_char(name) {
+            let name = name.to_string();
+            return Err(Error::InvalidKey { name, character });
+        }
+        let quoted = value.replace('\'', r"'\''");
+        script.push_str(&format!("export {}='{}'\n", name, quoted));
+    }
+    Ok(script)
+}
+
+/// First character of `name` which makes it an invalid shell identifier.
+fn shell_forbidden_char(name: &str) -> Option<char> {
+    name.char_indices()
+        .find(|&(i, c)| !(c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())))
+        .map(|(_, c)| c)
+}
+
+/// Configurable import of environment variables.
+///
+/// Variables whose name or value is not valid Unicode are skipped, as well
+/// as variables with names that [`validate_vars`](crate::validate_vars)
+/// would reject. Values are taken verbatim.
+#[derive(Clone, Debug, Default)]
+pub struct EnvImport {
+    prefix: Option<String>,
+    strip_prefix: bool,
+    allowed: Option<BTreeSet<String>>,
+    separator: Option<(String, String)>,
+    lowercase: bool,
+}
+
+impl EnvImport {
+    /// Create an importer accepting all variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only import variables whose name starts with `prefix`.
+    pub fn prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Whether to remove the prefix from imported names (default: `false`).
+    ///
+    /// Variables which are named exactly like the prefix are then skipped.
+    pub fn strip_prefix(mut self, strip_prefix: bool) -> Self {
+        self.strip_prefix = strip_prefix;
+        self
+    }
+
+    /// Only import the variables in `names`.
+    ///
+    /// Names are matched before the prefix is stripped.
+    pub fn allow<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Replace `separator` with `replacement` in imported names (default:
+    /// none), e.g. `__` with `.` for nested names.
+    ///
+    /// This applies after the prefix is stripped.
+    pub fn separator<S, R>(mut self, separator: S, replacement: R) -> Self
+    where
+        S: Into<String>,
+        R: Into<String>,
+    {
+        let separator = separator.into();
+        self.separator = match separator.as_str() {
+            "" => None,
+            _ => Some((separator, replacement.into())),
+        };
+        self
+    }
+
+    /// Whether to lowercase imported names (default: `false`).
+    ///
+    /// This applies after the prefix is stripped.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Import variables from the process environment.
+    pub fn collect(&self) -> HashMap<String, String> {
+        let vars = std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+        self.collect_from(vars)
+    }
+
+    /// Import variables from `vars`, e.g. a captured environment.
+    pub fn collect_from<I, K, V>(&self, vars: I) -> HashMap<String, String>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        vars.into_iter()
+            .filter_map(|(k, v)| Some((self.import_name(k.into())?, v.into())))
+            .collect()
+    }
+
+    /// Name to import variable `name` as, if it passes the filters.
+    fn import_name(&self, name: String) -> Option<String> {
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(&name) {
+                return None;
+            }
+        }
+        let name = match &self.prefix {
+            Some(prefix) if !name.starts_with(prefix.as_str()) => return None,
+            Some(prefix) if self.strip_prefix => name[prefix.len()..].to_string(),
+            _ => name,
+        };
+        let name = match &self.separator {
+            Some((separator, replacement)) => name.replace(separator.as_str(), replacement),
+            None => name,
+        };
+        let name = if self.lowercase {
+            name.to_lowercase()
+        } else {
+            name
+        };
+        if name.is_empty() || validate_key(&name).is_err() {
+            return None;
+        }
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("PATH", "/usr/bin"),
+            ("APP_HOST", "example.com"),
+            ("APP_PORT", "8080"),
+            ("APP_", "empty name"),
+            ("APP_${X}", "invalid name"),
+            ("VSCODE_NLS_CONFIG", r#"{"locale":"en-us"}"#),
+        ]
+    }
+
+    #[test]
+    fn import_filters() {
+        let vars = EnvImport::new().collect_from(environment());
+        assert_eq!(vars.len(), 5);
+        assert_eq!(vars["VSCODE_NLS_CONFIG"], r#"{"locale":"en-us"}"#);
+        assert!(!vars.contains_key("APP_${X}"));
+
+        let vars = EnvImport::new().prefix("APP_").collect_from(environment());
+        let mut names: Vec<_> = vars.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["APP_", "APP_HOST", "APP_PORT"]);
+
+        let importer = EnvImport::new().prefix("APP_").strip_prefix(true);
+        let vars = importer.collect_from(environment());
+        let mut names: Vec<_> = vars.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["HOST", "PORT"]);
+
+        let importer = importer.allow(vec!["APP_PORT", "PATH"]);
+        let vars = importer.collect_from(environment());
+        assert_eq!(vars.len(), 1);
+        assert_eq!(vars["PORT"], "8080");
+    }
+
+    #[test]
+    fn import_mangling() {
+        let environment = vec![
+            ("APP_DB__HOST", "db.example.com"),
+            ("APP_LOG_LEVEL", "info"),
+            ("OTHER__NAME", "other"),
+        ];
+        let importer = EnvImport::new()
+            .prefix("APP_")
+            .strip_prefix(true)
+            .separator("__", ".")
+            .lowercase(true);
+        let vars = importer.collect_from(environment.clone());
+        let mut names: Vec<_> = vars.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["db.host", "log_level"]);
+        assert_eq!(vars["db.host"], "db.example.com");
+
+        let vars = EnvImport::new()
+            .separator("__", ":")
+            .collect_from(environment);
+        assert_eq!(vars["OTHER:NAME"], "other");
+        assert!(vars.contains_key("APP_DB:HOST"));
+    }
+
+    #[test]
+    fn exports_script() {
+        let mut vars = HashMap::new();
+        vars.insert("B".to_string(), "$HOME `id` \"\\ \n".to_string());
+        vars.insert("A_1".to_string(), String::new());
+        vars.insert("_c".to_string(), "'".to_string());
+        vars.insert(String::new(), "skipped".to_string());
+        let script = shell_exports(&vars).unwrap();
+        let expected = [
+            "export A_1=''",
+            "export B='$HOME `id` \"\\ \n'",
+            r"export _c=''\'''",
+            "",
+        ];
+        assert_eq!(script, expected.join("\n"));
+        assert_eq!(shell_exports(&HashMap::new()).unwrap(), "");
+
+        for (name, character) in &[("db.host", '.'), ("1st", '1'), ("é", 'é')] {
+            let mut vars = HashMap::new();
+            vars.insert(name.to_string(), String::new());
+            match shell_exports(&vars).unwrap_err() {
+                Error::InvalidKey { character: c, .. } => assert_eq!(c, *character),
+                err => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn import_process_env() {
+        std::env::set_var("ENVSUBST_TEST_IMPORT", "value");
+        let vars = EnvImport::new().prefix("ENVSUBST_TEST_").collect();
+        assert_eq!(vars["ENVSUBST_TEST_IMPORT"], "value");
+        assert!(vars_from_env().contains_key("ENVSUBST_TEST_IMPORT"));
+    }
+}
@@ -0,0 +1,439 @@
+//! Library errors.
+
+use std::path::PathBuf;
+
+/// Library errors.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A variable name contains a forbidden character.
+    #[error("envsubst error: variable key '{name}' contains forbidden character '{character}'")]
+    InvalidKey {
+        /// Variable name.
+        name: String,
+        /// First forbidden character in the name.
+        character: char,
+    },
+    /// A variable value contains a forbidden character.
+    #[error(
+        "envsubst error: value of variable '{key}' contains forbidden character '{character}'"
+    )]
+    InvalidValue {
+        /// Variable name.
+        key: String,
+        /// First forbidden character in the value.
+        character: char,
+    },
+    /// Some variable names only differ by case or whitespace.
+    #[error(
+        "envsubst error: variable keys differing only by case or whitespace: {}",
+        format_collisions(.groups)
+    )]
+    KeyCollision {
+        /// Groups of colliding names, each sorted.
+        groups: Vec<Vec<String>>,
+    },
+    /// Some variable names are defined in several layers of a context.
+    #[error(
+        "envsubst error: variable keys defined in multiple layers: {}",
+        format_layers(.collisions)
+    )]
+    LayerCollision {
+        /// Names defined several times, sorted, with the indices of the
+        /// layers defining them.
+        collisions: Vec<(String, Vec<usize>)>,
+    },
+    /// Some variable values violate the constraints of a schema.
+    #[error(
+        "envsubst error: invalid variable values: {}",
+        format_violations(.violations)
+    )]
+    ConstraintViolation {
+        /// All violations, sorted by variable name.
+        violations: Vec<crate::schema::Violation>,
+    },
+    /// A placeholder references an undefined variable.
+    #[error(
+        "envsubst error: variable '{name}' is not defined (line {}: `{snippet}`){}",
+        .position.line,
+        format_hint(.hint)
+    )]
+    MissingVariable {
+        /// Variable name.
+        name: String,
+        /// Closest defined variable name, if any.
+        hint: Option<String>,
+        /// Position of the placeholder in the template.
+        position: Position,
+        /// Template text around the placeholder, on the same line.
+        snippet: String,
+    },
+    /// A required variable is undefined or empty, e.g. for `${foo:?message}`.
+    #[error("envsubst error: variable '{name}' is required{}", format_message(.message))]
+    RequiredVariable {
+        /// Variable name.
+        name: String,
+        /// Error message from the placeholder, possibly empty.
+        message: String,
+        /// Position of the placeholder in the template.
+        position: Position,
+    },
+    /// A template has a `${` which does not start a valid placeholder.
+    #[error(
+        "envsubst error: malformed placeholder at line {}, column {}",
+        .position.line,
+        .position.column
+    )]
+    MalformedPlaceholder {
+        /// Position of the `${` in the template.
+        position: Position,
+    },
+    /// A placeholder name is longer than allowed.
+    #[error(
+        "envsubst error: variable name '{name}' is {len} bytes long, over the limit of {limit} (line {})",
+        .position.line
+    )]
+    NameTooLong {
+        /// Start of the variable name, shortened with `...`.
+        name: String,
+        /// Length of the full name, in bytes.
+        len: usize,
+        /// Maximum length of names, in bytes.
+        limit: usize,
+        /// Position of the placeholder in the template.
+        position: Position,
+    },
+    /// A template has more placeholders than allowed.
+    #[error("envsubst error: template exceeds the limit of {limit} placeholders")]
+    TooManyPlaceholders {
+        /// Maximum number of placeholders.
+        limit: usize,
+        /// Position of the first placeholder over the limit.
+        position: Position,
+    },
+    /// A template has an unknown systemd specifier.
+    #[error("envsubst error: unknown specifier '%{specifier}'")]
+    UnknownSpecifier {
+        /// Specifier character.
+        specifier: char,
+        /// Position of the specifier in the template.
+        position: Position,
+    },
+    /// A placeholder uses an unknown filter, e.g. `${foo|unknown}`.
+    #[error("envsubst error: unknown filter '{filter}'")]
+    UnknownFilter {
+        /// Filter name.
+        filter: String,
+        /// Position of the placeholder in the template.
+        position: Position,
+    },
+    /// A filter could not transform a value.
+    #[error("envsubst error: filter '{filter}' failed: {message}")]
+    Filter {
+        /// Filter name.
+        filter: String,
+        /// Reason for the failure.
+        message: String,
+    },
+    /// Variable values reference each other in a cycle, in recursive mode.
+    #[error(
+        "envsubst error: variables reference each other in a cycle: {}",
+        .chain.join(" -> ")
+    )]
+    RecursionCycle {
+        /// Variable names in the cycle, starting and ending with the same one.
+        chain: Vec<String>,
+    },
+    /// Variable values are nested too deeply, in recursive mode.
+    #[error(
+        "envsubst error: expansion of variable '{name}' exceeds the maximum depth of {max_depth}"
+    )]
+    RecursionLimit {
+        /// Variable whose value could not be expanded.
+        name: String,
+        /// Maximum expansion depth.
+        max_depth: usize,
+    },
+    /// A render bundle could not be parsed.
+    #[error("envsubst error: invalid render bundle: {reason}")]
+    InvalidBundle {
+        /// Reason for the failure.
+        reason: String,
+    },
+    /// Input is not valid UTF-8.
+    #[error("envsubst error: input is not valid UTF-8")]
+    InvalidUtf8 {
+        /// Byte offset of the first invalid sequence.
+        offset: usize,
+    },
+    /// An I/O operation failed.
+    #[error("envsubst error: {}{source}", format_io(.operation, .path))]
+    Io {
+        /// Whether reading or writing failed.
+        operation: IoOperation,
+        /// Path being accessed, if any.
+        path: Option<PathBuf>,
+        /// Underlying error.
+        source: std::io::Error,
+    },
+}
+
+impl Error {
+    /// Locate this error at byte `offset` of `template`, for errors with
+    /// a position.
+    pub(crate) fn at(mut self, template: &str, offset: usize) -> Self {
+        match &mut self {
+            Error::MissingVariable {
+                position, snippet, ..
+            } => {
+                *position = Position::locate(template, offset);
+                *snippet = snippet_at(template, offset);
+            }
+            Error::RequiredVariable { position, .. }
+            | Error::MalformedPlaceholder { position }
+            | Error::NameTooLong { position, .. }
+            | Error::TooManyPlaceholders { position, .. }
+            | Error::UnknownSpecifier { position, .. }
+            | Error::UnknownFilter { position, .. } => {
+                *position = Position::locate(template, offset)
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Make positions relative to `base`, for errors on a fragment of a
+    /// template starting there.
+    pub(crate) fn shifted(mut self, base: &Position) -> Self {
+        match &mut self {
+            Error::MissingVariable { position, .. }
+            | Error::RequiredVariable { position, .. }
+            | Error::MalformedPlaceholder { position }
+            | Error::NameTooLong { position, .. }
+            | Error::TooManyPlaceholders { position, .. }
+            | Error::UnknownSpecifier { position, .. }
+            | Error::UnknownFilter { position, .. } => *position = base.join(position),
+            Error::InvalidUtf8 { offset } => *offset += base.offset,
+            _ => {}
+        }
+        self
+    }
+}
+
+/// Kind of a failed I/O operation, see [`Error::Io`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IoOperation {
+    /// Reading input, e.g. a template file.
+    Read,
+    /// Writing output.
+    Write,
+}
+
+/// A position within a template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// Byte offset, starting at 0.
+    pub offset: usize,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column (in characters), starting at 1.
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// Locate byte `offset` within `input`.
+    pub(crate) fn locate(input: &str, offset: usize) -> Self {
+        Self::default().advance(&input[..offset])
+    }
+
+    /// Position right after `text`, starting at this position.
+    pub(crate) fn advance(&self, text: &str) -> Self {
+        match text.rfind('\n') {
+            Some(last) => Self {
+                offset: self.offset + text.len(),
+                line: self.line + text.matches('\n').count(),
+                column: text[last + 1..].chars().count() + 1,
+            },
+            None => Self {
+                offset: self.offset + text.len(),
+                line: self.line,
+                column: self.column + text.chars().count(),
+            },
+        }
+    }
+
+    /// Position `relative` to this one, into an absolute position.
+    fn join(&self, relative: &Position) -> Self {
+        Self {
+            offset: self.offset + relative.offset,
+            line: self.line + relative.line - 1,
+            column: match relative.line {
+                1 => self.column + relative.column - 1,
+                _ => relative.column,
+            },
+        }
+    }
+}
+
+/// Maximum length of snippets, in bytes.
+const SNIPPET_MAX_LEN: usize = 60;
+
+/// Extract the line of `input` around byte `offset`, for error messages.
+///
+/// Long lines are shortened around `offset`, with `...` marking the cuts.
+pub(crate) fn snippet_at(input: &str, offset: usize) -> String {
+    let line_start = input[..offset].rfind('\n').map_or(0, |pos| pos + 1);
+    let line_end = input[offset..]
+        .find('\n')
+        .map_or(input.len(), |pos| offset + pos);
+    if line_end - line_start <= SNIPPET_MAX_LEN {
+        return input[line_start..line_end].trim().to_string();
+    }
+
+    let mut start = offset.saturating_sub(SNIPPET_MAX_LEN / 2).max(line_start);
+    while !input.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (start + SNIPPET_MAX_LEN).min(line_end);
+    while !input.is_char_boundary(end) {
+        end += 1;
+    }
+    let mut snippet = input[start..end].trim().to_string();
+    if start > line_start {
+        snippet.insert_str(0, "...");
+    }
+    if end < line_end {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Maximum length of names in errors, in characters.
+const NAME_MAX_LEN: usize = 32;
+
+/// Shorten `name` for error messages, with `...` marking the cut.
+pub(crate) fn truncate_name(name: &str) -> String {
+    match name.char_indices().nth(NAME_MAX_LEN) {
+        Some((end, _)) => format!("{}...", &name[..end]),
+        None => name.to_string(),
+    }
+}
+
+fn format_collisions(groups: &[Vec<String>]) -> String {
+    let groups: Vec<String> = groups
+        .iter()
+        .map(|keys| {
+            let quoted: Vec<String> = keys.iter().map(|k| format!("'{}'", k)).collect();
+            quoted.join(", ")
+        })
+        .collect();
+    groups.join("; ")
+}
+
+fn format_layers(collisions: &[(String, Vec<usize>)]) -> String {
+    let collisions: Vec<String> = collisions
+        .iter()
+        .map(|(key, layers)| {
+            let layers: Vec<String> = layers.iter().map(usize::to_string).collect();
+            format!("'{}' (layers {})", key, layers.join(", "))
+        })
+        .collect();
+    collisions.join("; ")
+}
+
+fn format_violations(violations: &[crate::schema::Violation]) -> String {
+    let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    violations.join("; ")
+}
+
+fn format_hint(hint: &Option<String>) -> String {
+    match hint {
+        Some(hint) => format!(", did you mean '{}'?", hint),
+        None => String::new(),
+    }
+}
+
+fn format_message(message: &str) -> String {
+    match message {
+        "" => String::new(),
+        message => format!(": {}", message),
+    }
+}
+
+fn format_io(operation: &IoOperation, path: &Option<PathBuf>) -> String {
+    let (verb, stream) = match operation {
+        IoOperation::Read => ("read", "input"),
+        IoOperation::Write => ("write", "output"),
+    };
+    match path {
+        Some(path) => format!("failed to {} '{}': ", verb, path.display()),
+        None => format!("failed to {} {}: ", verb, stream),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let input = "ab\ncdé\n\nf";
+        let at = |offset| {
+            let p = Position::locate(input, offset);
+            (p.line, p.column)
+        };
+        assert_eq!(at(0), (1, 1));
+        assert_eq!(at(2), (1, 3));
+        assert_eq!(at(3), (2, 1));
+        assert_eq!(at(7), (2, 4));
+        assert_eq!(at(9), (4, 1));
+
+        let base = Position::locate(input, 5);
+        let err = Error::InvalidUtf8 { offset: 2 }.shifted(&base);
+        assert!(matches!(err, Error::InvalidUtf8 { offset: 7 }));
+
+        let shift = |offset| {
+            let p = base.join(&Position::locate(&input[5..], offset));
+            (p.offset, p.line, p.column)
+        };
+        let expected = |offset| {
+            let p = Position::locate(input, offset);
+            (p.offset, p.line, p.column)
+        };
+        for offset in &[5, 7, 8, 9, 10] {
+            assert_eq!(shift(offset - 5), expected(*offset));
+        }
+    }
+
+    #[test]
+    fn snippets() {
+        let input = "first\n  host = ${DB_HOST}  \nlast";
+        assert_eq!(snippet_at(input, 15), "host = ${DB_HOST}");
+        assert_eq!(snippet_at(input, 0), "first");
+
+        let long = format!("{}${{X}}{}", "a".repeat(50), "é".repeat(50));
+        let snippet = snippet_at(&long, 50);
+        assert!(snippet.starts_with("...aaa"));
+        assert!(snippet.contains("${X}"));
+        assert!(snippet.ends_with("é..."));
+    }
+
+    #[test]
+    fn truncated_names() {
+        assert_eq!(truncate_name("HOST"), "HOST");
+        assert_eq!(truncate_name(&"a".repeat(32)), "a".repeat(32));
+        let name = truncate_name(&"é".repeat(100));
+        assert_eq!(name, format!("{}...", "é".repeat(32)));
+    }
+}
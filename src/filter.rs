@@ -0,0 +1,133 @@
+//! Value filters, for placeholders like `${foo|upper}`.
+
+use crate::{CaseFolding, Error};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A value transformation, see [`Substitutor::register_filter`](crate::Substitutor::register_filter).
+pub(crate) type FilterFn = dyn Fn(&str) -> Result<String, Error> + Send + Sync;
+
+/// A filter, case conversions depending on the configured folding.
+#[derive(Clone)]
+pub(crate) enum Filter {
+    Lower,
+    Upper,
+    Custom(Arc<FilterFn>),
+}
+
+/// Filters available by name.
+pub(crate) type Filters = BTreeMap<String, Filter>;
+
+/// Built-in filters: `lower`, `upper`, `trim` and `base64`.
+pub(crate) fn builtins() -> Filters {
+    let mut filters = Filters::new();
+    filters.insert("lower".to_string(), Filter::Lower);
+    filters.insert("upper".to_string(), Filter::Upper);
+    let mut add = |name: &str, filter: fn(&str) -> String| {
+        let filter: Arc<FilterFn> = Arc::new(move |value: &str| Ok(filter(value)));
+        filters.insert(name.to_string(), Filter::Custom(filter));
+    };
+    add("trim", |value| value.trim().to_string());
+    add("base64", base64);
+    filters
+}
+
+/// Split a placeholder name like `foo | upper` into the variable name and
+/// the filter names.
+///
+/// Whitespace around `|` separators is ignored; names without filters are
+/// left untouched.
+pub(crate) fn split_filters(name: &str) -> (&str, Vec<&str>) {
+    match name.find('|') {
+        Some(sep) => (
+            name[..sep].trim(),
+            name[sep + 1..].split('|').map(str::trim).collect(),
+        ),
+        None => (name, Vec::new()),
+    }
+}
+
+/// Apply `names` filters in order to `value`, failing on unknown filters.
+pub(crate) fn apply(
+    filters: &Filters,
+    names: &[&str],
+    value: &str,
+    folding: CaseFolding,
+) -> Result<String, Error> {
+    let mut value = value.to_string();
+    for name in names {
+        let filter = filters.get(*name).ok_or_else(|| Error::UnknownFilter {
+            filter: name.to_string(),
+            position: Default::default(),
+        })?;
+        value = match filter {
+            Filter::Lower => folding.lower(&value),
+            Filter::Upper => folding.upper(&value),
+            Filter::Custom(filter) => filter(&value)?,
+        };
+    }
+    Ok(value)
+}
+
+/// Standard base64 encoding, with padding.
+fn base64(value: &str) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((value.len() + 2) / 3 * 4);
+    for chunk in value.as_bytes().chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_filters() {
+        let filters = builtins();
+        let run =
+            |names: &[&str], value| apply(&filters, names, value, CaseFolding::Unicode).unwrap();
+        assert_eq!(run(&["lower"], "Example.COM"), "example.com");
+        assert_eq!(run(&["trim", "upper"], "  ab c "), "AB C");
+        assert_eq!(run(&[], " as is "), " as is ");
+
+        let encoded: Vec<_> = ["", "f", "fo", "foo", "foob", "fooba", "foobar"]
+            .iter()
+            .map(|value| run(&["base64"], value))
+            .collect();
+        assert_eq!(
+            encoded,
+            vec!["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"]
+        );
+
+        assert_eq!(run(&["upper"], "straße"), "STRASSE");
+        let ascii = apply(&filters, &["upper"], "straße", CaseFolding::Ascii);
+        assert_eq!(ascii.unwrap(), "STRAßE");
+
+        let err = apply(&filters, &["trim", "rot13"], "x", CaseFolding::Unicode).unwrap_err();
+        assert!(matches!(err, Error::UnknownFilter { filter, .. } if filter == "rot13"));
+    }
+
+    #[test]
+    fn filter_names() {
+        assert_eq!(split_filters(" HOST "), (" HOST ", vec![]));
+        assert_eq!(
+            split_filters("HOST | lower|trim"),
+            ("HOST", vec!["lower", "trim"])
+        );
+        assert_eq!(split_filters("HOST|"), ("HOST", vec![""]));
+    }
+}
@@ -0,0 +1,113 @@
+//! Immutable variables.
+
+use crate::{validate_key, Error, VariableResolver};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// Variables which cannot change once built, for sharing across renders and
+/// threads.
+///
+/// Names are validated upfront, and the variables are only ever read by
+/// rendering, in any mode. Clones are cheap and share the same storage, and
+/// contexts with the same variables are equal and hash the same, e.g. to key
+/// a cache of rendered outputs.
+///
+/// ```rust
+/// use envsubst::{FrozenContext, Substitutor};
+///
+/// let context = FrozenContext::new(vec![("host", "example.com"), ("port", "8080")]).unwrap();
+/// let shared = context.clone();
+/// let out = std::thread::spawn(move || {
+///     Substitutor::new().render_resolver("${host}:${port}", &shared).unwrap()
+/// });
+/// assert_eq!(out.join().unwrap(), "example.com:8080");
+/// assert_eq!(context.get("port"), Some("8080"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FrozenContext {
+    variables: Arc<BTreeMap<String, String>>,
+}
+
+impl FrozenContext {
+    /// Freeze `variables`, failing if any name is invalid.
+    ///
+    /// If a name appears multiple times, its last value is used.
+    pub fn new<I, K, V>(variables: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Display,
+    {
+        let mut frozen = BTreeMap::new();
+        for (k, v) in variables {
+            let k = k.into();
+            validate_key(&k)?;
+            frozen.insert(k, v.to_string());
+        }
+        Ok(Self {
+            variables: Arc::new(frozen),
+        })
+    }
+
+    /// Value of variable `name`, if defined.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// All variables, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.variables.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Number of variables.
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    /// Whether there are no variables.
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+}
+
+impl VariableResolver for FrozenContext {
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get(name).map(Cow::Borrowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    #[test]
+    fn frozen_contexts() {
+        let hash = |context: &FrozenContext| {
+            let mut hasher = DefaultHasher::new();
+            context.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let context = FrozenContext::new(vec![("b", 2), ("a", 1), ("b", 3)]).unwrap();
+        assert_eq!(
+            context.iter().collect::<Vec<_>>(),
+            vec![("a", "1"), ("b", "3")]
+        );
+        let reordered = FrozenContext::new(vec![("b", "3"), ("a", "1")]).unwrap();
+        assert_eq!(context, reordered);
+        assert_eq!(hash(&context), hash(&reordered));
+        assert_ne!(hash(&context), hash(&FrozenContext::default()));
+
+        let engine = crate::Substitutor::new().recursive(2);
+        let out = engine.render_resolver("${a}${b}${c}", &context).unwrap();
+        assert_eq!(out, "13${c}");
+        assert_eq!((context.len(), context.get("c")), (2, None));
+
+        let err = FrozenContext::new(vec![("${a}", "")]).unwrap_err();
+        assert!(matches!(err, Error::InvalidKey { .. }));
+    }
+}
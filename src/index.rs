@@ -0,0 +1,119 @@
+//! Variables usage indexing across templates.
+//!
+//! This builds a reverse index from variable names to the templates (and
+//! locations within them) where they are referenced, e.g. to answer "what
+//! breaks if I remove `FOO`?".
+//!
+//! ```rust
+//! use envsubst::index::UsageIndex;
+//!
+//! let index = UsageIndex::from_templates(vec![
+//!     ("url", "${scheme}://${host}/"),
+//!     ("motd", "Welcome to ${host}"),
+//! ]);
+//! let users: Vec<_> = index.templates_using("host").into_iter().collect();
+//! assert_eq!(users, vec!["motd", "url"]);
+//! ```
+
+use crate::iter_placeholders;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// A single variable reference within a named template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Usage {
+    /// Name of the template containing the reference.
+    pub template: String,
+    /// Byte range of the placeholder within the template.
+    pub span: Range<usize>,
+}
+
+/// Reverse index from variable names to their usages.
+#[derive(Clone, Debug, Default)]
+pub struct UsageIndex {
+    usages: BTreeMap<String, Vec<Usage>>,
+}
+
+impl UsageIndex {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an index from a set of `(name, template)` pairs.
+    pub fn from_templates<I, N, T>(templates: I) -> Self
+    where
+        I: IntoIterator<Item = (N, T)>,
+        N: Into<String>,
+        T: AsRef<str>,
+    {
+        let mut index = Self::new();
+        for (name, template) in templates {
+            index.add(name, template);
+        }
+        index
+    }
+
+    /// Add all variable references in `template`, under the given `name`.
+    pub fn add<N, T>(&mut self, name: N, template: T)
+    where
+        N: Into<String>,
+        T: AsRef<str>,
+    {
+        let name = name.into();
+        for placeholder in iter_placeholders(template.as_ref()) {
+            self.usages
+                .entry(placeholder.name().to_string())
+                .or_default()
+                .push(Usage {
+                    template: name.clone(),
+                    span: placeholder.span(),
+                });
+        }
+    }
+
+    /// All indexed variable names, in sorted order.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.usages.keys().map(String::as_str)
+    }
+
+    /// All usages of `variable`, in insertion order.
+    pub fn usages(&self, variable: &str) -> &[Usage] {
+        match self.usages.get(variable) {
+            Some(usages) => usages,
+            None => &[],
+        }
+    }
+
+    /// Names of templates referencing `variable`.
+    pub fn templates_using(&self, variable: &str) -> BTreeSet<&str> {
+        self.usages(variable)
+            .iter()
+            .map(|u| u.template.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_index() {
+        let mut index = UsageIndex::new();
+        index.add("a.conf", "${HOST}:${PORT} ${HOST}");
+        index.add("b.conf", "port=${PORT}");
+
+        let vars: Vec<_> = index.variables().collect();
+        assert_eq!(vars, vec!["HOST", "PORT"]);
+
+        let usages = index.usages("HOST");
+        assert_eq!(usages.len(), 2);
+        assert_eq!(usages[1].template, "a.conf");
+        assert_eq!(usages[1].span, 16..23);
+
+        let users: Vec<_> = index.templates_using("PORT").into_iter().collect();
+        assert_eq!(users, vec!["a.conf", "b.conf"]);
+        assert!(index.usages("MISSING").is_empty());
+    }
+}
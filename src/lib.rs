@@ -5,8 +5,16 @@
 //! all matching placeholders.
 //!
 //! Its name and logic is similar to the [`envsubst`] GNU utility, but
-//! this only supports braces-delimited variables (i.e. `${foo}`) and
-//! takes replacement values from an explicit map of variables.
+//! by default this only supports braces-delimited variables (i.e. `${foo}`,
+//! or `${foo:-default}` with a fallback value) and takes replacement values
+//! from an explicit map of variables. Other syntaxes and behaviors can be
+//! configured through a [`Substitutor`], and templates rendered many times
+//! can be parsed once into a [`Template`].
+//!
+//! A literal placeholder can be produced by doubling its dollar sign, i.e.
+//! `$${foo}` renders as `${foo}`. Required variables can be marked as such
+//! with `${foo:?message}`, which fails the substitution with `message` if
+//! `foo` is undefined or empty.
 //!
 //! [`envsubst`]: https://www.gnu.org/software/gettext/manual/html_node/envsubst-Invocation.html
 //!
@@ -26,87 +34,479 @@
 //! assert!(!envsubst::is_templated(&final_url));
 //! assert_eq!(final_url, "https://example.com/login");
 //! ```
+//!
+//! ## Guarantees
+//!
+//! The following properties hold for [`substitute`] and are covered by
+//! tests, so downstream users can rely on them:
+//!
+//!  * substituting with an empty map returns the template unchanged, as
+//!    long as it has no placeholders with defaults nor escape sequences;
+//!  * a template for which [`is_templated`] is `false` is returned
+//!    unchanged, whatever the variables, unless it has escape sequences;
+//!  * text outside of placeholders and escape sequences is copied
+//!    byte-for-byte, and each placeholder found by [`iter_placeholders`] is
+//!    replaced by its value verbatim, or by its default if the variable is
+//!    not defined, or left untouched otherwise;
+//!  * [`escape`]d text is always rendered back verbatim, i.e.
+//!    `substitute(escape(s), vars) == s`;
+//!  * substituted values are never expanded again, and the output does not
+//!    depend on the iteration order of the variables map.
 
 #![allow(clippy::implicit_hasher)]
 
-use std::collections::HashMap;
+pub mod bundle;
+mod detect;
+pub mod env;
+mod error;
+mod filter;
+mod frozen;
+pub mod index;
+pub mod lint;
+mod placeholder;
+pub mod plan;
+pub mod preview;
+mod report;
+pub mod resolver;
+pub mod schema;
+mod scrub;
+pub mod search;
+mod stream;
+mod substitutor;
+pub mod systemd;
+mod template;
+
+pub use detect::{detect_syntax, Notation, SyntaxGuess};
+pub use error::{Error, IoOperation, Position};
+pub use frozen::FrozenContext;
+pub use placeholder::{iter_placeholders, Escape, Placeholder, Placeholders, Syntax};
+pub use report::SubstitutionReport;
+pub use resolver::VariableResolver;
+pub use scrub::scrub;
+pub use substitutor::{CaseFolding, Missing, Substitutor};
+pub use template::Template;
 
-/// Library errors.
-#[derive(thiserror::Error, Debug)]
-#[error("envsubst error: {0}")]
-pub struct Error(String);
+use placeholder::{Scanner, Token};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
 
 /// Substitute variables in a template string.
 ///
 /// Given an input string `template`, replace tokens of the form `${foo}` with
-/// values provided in `variables`.
+/// values provided in `variables`. Tokens of the form `${foo:-default}` are
+/// replaced with the literal `default` if `foo` is not in `variables`, and
+/// tokens of the form `${foo:?message}` fail with [`Error::RequiredVariable`]
+/// if `foo` is not in `variables` or is empty.
+/// Escaped tokens of the form `$${foo}` are rendered as a literal `${foo}`.
+///
+/// The template is scanned in a single pass, so the result does not depend
+/// on the iteration order of `variables` and substituted values are never
+/// themselves expanded.
+///
+/// For other collections of variables, see [`substitute_iter`]. For other
+/// syntaxes and policies, see [`Substitutor`].
 pub fn substitute<T>(template: T, variables: &HashMap<String, String>) -> Result<String, Error>
 where
     T: Into<String>,
 {
-    let mut output = template.into();
-    if variables.is_empty() {
-        return Ok(output);
-    }
+    Substitutor::new().render(template.into(), variables)
+}
+
+/// Substitute variables from any map-like collection in a template string.
+///
+/// This behaves like [`substitute`], but variables can be any collection of
+/// names and displayable values, e.g. a `BTreeMap<&str, u16>` or a slice of
+/// pairs. If a name appears multiple times, its last value is used.
+///
+/// ```rust
+/// let vars = vec![("host", "example.com"), ("port", "443")];
+/// let out = envsubst::substitute_iter("${host}:${port}", vars).unwrap();
+/// assert_eq!(out, "example.com:443");
+/// ```
+pub fn substitute_iter<T, I, K, V>(template: T, variables: I) -> Result<String, Error>
+where
+    T: Into<String>,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: Display,
+{
+    let variables: HashMap<String, String> = variables
+        .into_iter()
+        .map(|(k, v)| (k.as_ref().to_string(), v.to_string()))
+        .collect();
+    Substitutor::new().render(template.into(), &variables)
+}
 
-    for (k, v) in variables {
-        validate(k, "key")?;
-        validate(v, "value")?;
+/// Substitute variables in a template string, including bare `$foo` tokens.
+///
+/// This behaves like [`substitute`], but also recognizes placeholders of
+/// the form `$foo` like the GNU utility, i.e. using [`Syntax::Dollar`]. Bare
+/// names follow POSIX identifier rules, and stop at the first character
+/// which is not an ASCII letter, digit or underscore.
+///
+/// Unlike the GNU utility, undefined variables are left untouched. For the
+/// GNU behavior, use a [`Substitutor`] with [`Missing::Empty`].
+///
+/// ```rust
+/// let vars = vec![("HOST", "example.com"), ("PORT", "443")];
+/// let out = envsubst::substitute_posix("$HOST:$PORT/${HOST}.$1 $HOSTNAME", vars).unwrap();
+/// assert_eq!(out, "example.com:443/example.com.$1 $HOSTNAME");
+/// ```
+pub fn substitute_posix<T, I, K, V>(template: T, variables: I) -> Result<String, Error>
+where
+    T: AsRef<str>,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: Display,
+{
+    let variables: HashMap<String, String> = variables
+        .into_iter()
+        .map(|(k, v)| (k.as_ref().to_string(), v.to_string()))
+        .collect();
+    Substitutor::new()
+        .syntax(Syntax::Dollar)
+        .render(template, &variables)
+}
 
-        let from = format!("${{{}}}", k);
-        output = output.replace(&from, v)
-    }
+/// Substitute variables while copying `reader` to `writer`.
+///
+/// This behaves like [`substitute`] on the whole input, without loading it
+/// all in memory. See [`Substitutor::render_stream`] for details.
+pub fn substitute_stream<R, W>(
+    reader: R,
+    writer: W,
+    variables: &HashMap<String, String>,
+) -> Result<(), Error>
+where
+    R: std::io::BufRead,
+    W: std::io::Write,
+{
+    Substitutor::new().render_stream(reader, writer, variables)
+}
 
-    Ok(output)
+/// Substitute variables in a template string, reporting variables usage.
+///
+/// This behaves like [`substitute`], but also reports which placeholders
+/// were found in the template and which variables were substituted, e.g.
+/// to warn about unused variables or unmatched placeholders.
+///
+/// ```rust
+/// let mut context = std::collections::HashMap::new();
+/// context.insert("host".to_string(), "example.com".to_string());
+/// context.insert("unused".to_string(), "value".to_string());
+///
+/// let report = envsubst::substitute_with_report("${host}:${port}", &context).unwrap();
+/// assert_eq!(report.output, "example.com:${port}");
+/// assert!(report.unmatched().contains("port"));
+/// assert!(report.unused(&context).contains("unused"));
+/// ```
+pub fn substitute_with_report<T>(
+    template: T,
+    variables: &HashMap<String, String>,
+) -> Result<SubstitutionReport, Error>
+where
+    T: AsRef<str>,
+{
+    let mut report = SubstitutionReport::default();
+    report.output = Substitutor::new().render_with(
+        template.as_ref(),
+        variables,
+        |placeholder, substituted| {
+            if substituted {
+                report.substituted.insert(placeholder.name().to_string());
+            }
+            report.placeholders.insert(placeholder.name().to_string());
+        },
+    )?;
+    Ok(report)
 }
 
 /// Check whether input string contains templated variables.
+///
+/// This is consistent with [`substitute`], i.e. it checks whether `input`
+/// contains at least one placeholder that could be substituted. Escaped
+/// placeholders (e.g. `$${foo}`) do not count as templated.
 pub fn is_templated<S>(input: S) -> bool
 where
     S: AsRef<str>,
 {
-    let start = input.as_ref().find("${");
-    let end = input.as_ref().find('}');
+    iter_placeholders(input.as_ref()).next().is_some()
+}
+
+/// Extract all placeholders of a template, checking that it is well-formed.
+///
+/// This is like [`iter_placeholders`], but fails with
+/// [`Error::MalformedPlaceholder`] if the template has a `${` which does not
+/// start a valid placeholder, e.g. in `${foo` or `${a${b}}`, which would
+/// otherwise be silently rendered verbatim. Placeholders come in order, with
+/// their name and byte span, and escaped placeholders are skipped.
+///
+/// ```rust
+/// let found = envsubst::extract_variables("${host}:${port:-80}").unwrap();
+/// let names: Vec<_> = found.iter().map(|p| p.name()).collect();
+/// assert_eq!(names, vec!["host", "port"]);
+/// assert_eq!(found[1].span(), 8..19);
+///
+/// assert!(envsubst::extract_variables("${host:${port}").is_err());
+/// ```
+pub fn extract_variables(template: &str) -> Result<Vec<Placeholder<'_>>, Error> {
+    // Text between placeholders and escapes must not have any `${` left.
+    let check = |start: usize, end: usize| match template[start..end].find("${") {
+        Some(offset) => Err(Error::MalformedPlaceholder {
+            position: Position::locate(template, start + offset),
+        }),
+        None => Ok(()),
+    };
 
-    match (start, end) {
-        (Some(s), Some(e)) => s < e,
-        _ => false,
+    let mut found = Vec::new();
+    let mut last = 0;
+    for token in Scanner::new(template, Syntax::Braced, Escape::DoubleDollar) {
+        let span = token.span();
+        check(last, span.start)?;
+        last = span.end;
+        if let Token::Placeholder(placeholder) = token {
+            found.push(placeholder);
+        }
     }
+    check(last, template.len())?;
+    Ok(found)
+}
+
+/// Check whether input string contains templated variables for `substitutor`.
+///
+/// This is like [`is_templated`], but follows the syntax and escaping rules
+/// configured in `substitutor`.
+///
+/// ```rust
+/// use envsubst::{Substitutor, Syntax};
+///
+/// let engine = Substitutor::new().syntax(Syntax::DoubleBraces);
+/// assert!(envsubst::is_templated_with("Hello {{ name }}", &engine));
+/// assert!(!envsubst::is_templated_with("Hello ${name}", &engine));
+/// ```
+pub fn is_templated_with<S>(input: S, substitutor: &Substitutor) -> bool
+where
+    S: AsRef<str>,
+{
+    Scanner::new(input.as_ref(), substitutor.syntax, substitutor.escape)
+        .any(|token| matches!(token, Token::Placeholder(_)))
+}
+
+/// Escape a string, so that it renders verbatim.
+///
+/// This doubles the dollar sign of anything which could be interpreted as
+/// a placeholder, so that [`substitute`] produces back the original input.
+///
+/// ```rust
+/// let escaped = envsubst::escape("literal ${foo}");
+/// assert_eq!(escaped, "literal $${foo}");
+/// assert!(!envsubst::is_templated(&escaped));
+///
+/// let vars = vec![("foo", "bar")];
+/// assert_eq!(envsubst::substitute_iter(escaped, vars).unwrap(), "literal ${foo}");
+/// ```
+pub fn escape<S>(input: S) -> String
+where
+    S: AsRef<str>,
+{
+    input.as_ref().replace("${", "$${")
 }
 
 /// Validate variables for substitution.
 ///
-/// This check whether substitution variables are valid. In order to make
-/// substitution deterministic, the following characters are not allowed
-/// within variables names nor values: `$`, `{`, `}`.
+/// This check whether substitution variables are valid. Variables names
+/// cannot be referenced by a placeholder if they contain any of the
+/// following characters, so those are not allowed: `$`, `{`, `}`.
+///
+/// Values can contain any character (e.g. JSON documents), as they are
+/// never re-interpreted as placeholders.
+///
+/// For other collections of variables, see [`validate_vars_iter`].
 pub fn validate_vars(variables: &HashMap<String, String>) -> Result<(), Error> {
-    for (k, v) in variables {
-        validate(k, "key")?;
-        validate(v, "value")?;
+    for k in variables.keys() {
+        validate_key(k)?;
     }
     Ok(())
 }
 
-/// Check whether `value` contains invalid characters.
-fn validate<S>(value: S, kind: &str) -> Result<(), Error>
+/// Check whether substitution variables from any collection are valid.
+///
+/// This behaves like [`validate_vars`], but works on borrowed data as well,
+/// without allocating:
+///
+/// ```rust
+/// let pairs = [("HOST", "example.com"), ("PORT", "443")];
+/// assert!(envsubst::validate_vars_iter(pairs.iter().copied()).is_ok());
+///
+/// let invalid = [("${HOST}", "example.com")];
+/// assert!(envsubst::validate_vars_iter(invalid.iter().copied()).is_err());
+///
+/// // Process environment names can be checked without building a map.
+/// let names = std::env::vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v)));
+/// let _ = envsubst::validate_vars_iter(names);
+/// ```
+pub fn validate_vars_iter<I, K, V>(variables: I) -> Result<(), Error>
 where
-    S: AsRef<str>,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
 {
-    let forbidden = &["$", "{", "}"];
-    for c in forbidden {
-        if value.as_ref().contains(c) {
-            let err_msg = format!(
-                "variable {} '{}' contains forbidden character '{}'",
-                kind,
-                value.as_ref(),
-                c
-            );
-            return Err(Error(err_msg));
-        };
+    for (k, _) in variables {
+        validate_key(k.as_ref())?;
+    }
+    Ok(())
+}
+
+/// Check substitution variables for colliding names.
+///
+/// Keys which only differ by case or by leading/trailing whitespace
+/// (e.g. `HOST` and `host `) are accepted by `substitute`, but are very
+/// likely a mistake in how the context was assembled. This reports all
+/// such groups of keys.
+pub fn validate_key_collisions(variables: &HashMap<String, String>) -> Result<(), Error> {
+    let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for k in variables.keys() {
+        groups
+            .entry(k.trim().to_lowercase())
+            .or_default()
+            .push(k.as_str());
+    }
+
+    let collisions: Vec<Vec<String>> = groups
+        .values_mut()
+        .filter(|keys| keys.len() > 1)
+        .map(|keys| {
+            keys.sort_unstable();
+            keys.iter().map(|k| k.to_string()).collect()
+        })
+        .collect();
+    if !collisions.is_empty() {
+        return Err(Error::KeyCollision { groups: collisions });
     }
     Ok(())
 }
 
+/// Check layered substitution variables for names defined in several layers.
+///
+/// Contexts are often assembled from layers, e.g. defaults, then a config
+/// file, then the environment, with later layers silently overriding earlier
+/// ones. This reports all names defined in more than one of `layers`, with
+/// the indices of the layers defining them, so that overrides can be made
+/// explicit. Names are compared exactly; see [`validate_key_collisions`] to
+/// also catch names differing by case or whitespace in the merged context.
+///
+/// ```rust
+/// use std::collections::HashMap;
+///
+/// let mut defaults = HashMap::new();
+/// defaults.insert("HOST".to_string(), "localhost".to_string());
+/// let mut overrides = HashMap::new();
+/// overrides.insert("HOST".to_string(), "example.com".to_string());
+///
+/// let err = envsubst::validate_layer_collisions(&[&defaults, &overrides]).unwrap_err();
+/// assert!(matches!(err, envsubst::Error::LayerCollision { .. }));
+/// ```
+pub fn validate_layer_collisions(layers: &[&HashMap<String, String>]) -> Result<(), Error> {
+    let mut defined: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, layer) in layers.iter().enumerate() {
+        for k in layer.keys() {
+            defined.entry(k.as_str()).or_default().push(index);
+        }
+    }
+
+    let collisions: Vec<(String, Vec<usize>)> = defined
+        .into_iter()
+        .filter(|(_, indices)| indices.len() > 1)
+        .map(|(k, indices)| (k.to_string(), indices))
+        .collect();
+    if !collisions.is_empty() {
+        return Err(Error::LayerCollision { collisions });
+    }
+    Ok(())
+}
+
+/// Check whether `name` is a plain identifier (`[A-Za-z_][A-Za-z0-9_]*`).
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Find the candidate closest to `name`, for "did you mean" hints.
+///
+/// Distance is computed case-insensitively, and only reasonably close
+/// candidates are considered. Ties are broken by iteration order.
+pub(crate) fn closest_match<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let threshold = std::cmp::max(1, name.len() / 3);
+
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        let lowered: Vec<char> = candidate.to_lowercase().chars().collect();
+        let distance = edit_distance(&name, &lowered);
+        if distance > threshold || best.map_or(false, |(d, _)| d <= distance) {
+            continue;
+        }
+        best = Some((distance, candidate));
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+/// Edit distance between two strings, counting adjacent transpositions.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut best = std::cmp::min(d[i - 1][j] + 1, d[i][j - 1] + 1);
+            best = std::cmp::min(best, d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = std::cmp::min(best, d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = best;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Find the first character which is forbidden in variable names.
+fn forbidden_char(input: &str) -> Option<char> {
+    input.chars().find(|c| matches!(c, '$' | '{' | '}'))
+}
+
+/// Check that a variable name has no forbidden characters.
+pub(crate) fn validate_key(name: &str) -> Result<(), Error> {
+    match forbidden_char(name) {
+        Some(character) => Err(Error::InvalidKey {
+            name: name.to_string(),
+            character,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Check that a variable value has no forbidden characters.
+pub(crate) fn validate_value(key: &str, value: &str) -> Result<(), Error> {
+    match forbidden_char(value) {
+        Some(character) => Err(Error::InvalidValue {
+            key: key.to_string(),
+            character,
+        }),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -123,6 +523,105 @@ mod tests {
         assert_eq!(out, expected);
     }
 
+    #[test]
+    fn single_pass_subst() {
+        let template = "${${A}} ${B}${A}";
+        let mut env = HashMap::new();
+        env.insert("A".to_string(), "B".to_string());
+        env.insert("B".to_string(), "b".to_string());
+
+        let out = substitute(template, &env).unwrap();
+        assert_eq!(out, "${B} bB");
+    }
+
+    #[test]
+    fn default_subst() {
+        let template = "${HOST:-localhost}:${PORT:-8080}${PATH:-}/${NAME:-a default value}";
+        let mut env = HashMap::new();
+        env.insert("PORT".to_string(), "443".to_string());
+
+        let out = substitute(template, &env).unwrap();
+        assert_eq!(out, "localhost:443/a default value");
+
+        let out = substitute(template, &HashMap::new()).unwrap();
+        assert_eq!(out, "localhost:8080/a default value");
+    }
+
+    #[test]
+    fn report_subst() {
+        let template = "${A} ${B:-b} ${C} ${A}";
+        let mut env = HashMap::new();
+        env.insert("A".to_string(), "a".to_string());
+        env.insert("D".to_string(), "d".to_string());
+
+        let report = substitute_with_report(template, &env).unwrap();
+        assert_eq!(report.output, "a b ${C} a");
+        assert_eq!(report.substituted.len(), 1);
+        assert!(report.substituted.contains("A"));
+        assert_eq!(report.placeholders.len(), 3);
+        assert_eq!(
+            report.unmatched().into_iter().collect::<Vec<_>>(),
+            vec!["B", "C"]
+        );
+        assert_eq!(
+            report.unused(&env).into_iter().collect::<Vec<_>>(),
+            vec!["D"]
+        );
+
+        let mut replacements = HashMap::new();
+        replacements.insert("C".to_string(), "E".to_string());
+        replacements.insert("D".to_string(), "F".to_string());
+        assert_eq!(
+            report.deprecated(&replacements),
+            vec!["variable 'C' is deprecated, use 'E' instead"]
+        );
+    }
+
+    #[test]
+    fn generic_vars() {
+        enum Proto {
+            Https,
+        }
+        impl Display for Proto {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match self {
+                    Proto::Https => write!(f, "https"),
+                }
+            }
+        }
+
+        let template = "${proto}://${host}:${port}";
+        let mut env = BTreeMap::new();
+        env.insert("host", "example.com".to_string());
+        env.insert("port", 443.to_string());
+        validate_vars_iter(&env).unwrap();
+
+        let out = substitute_iter(template, &env).unwrap();
+        assert_eq!(out, "${proto}://example.com:443");
+
+        let vars = vec![("proto", Proto::Https)];
+        let out = substitute_iter(template, vars).unwrap();
+        assert_eq!(out, "https://${host}:${port}");
+
+        let path = std::path::PathBuf::from("/etc/app.conf");
+        let out = substitute_iter("cat ${path}", [("path", path.display())]).unwrap();
+        assert_eq!(out, "cat /etc/app.conf");
+    }
+
+    #[test]
+    fn escaped_subst() {
+        let template = "${VAR} $${VAR} $$${VAR} $$ $5";
+        let mut env = HashMap::new();
+        env.insert("VAR".to_string(), "var".to_string());
+
+        let out = substitute(template, &env).unwrap();
+        assert_eq!(out, "var ${VAR} $${VAR} $$ $5");
+
+        assert!(!is_templated("$${VAR}"));
+        assert!(is_templated("$${VAR} ${VAR}"));
+        assert_eq!(escape("$${VAR} $5"), "$$${VAR} $5");
+    }
+
     #[test]
     fn template_check() {
         let plain = "foo";
@@ -136,6 +635,154 @@ mod tests {
 
         let ending = "foo}";
         assert!(!is_templated(ending));
+
+        let reversed = "} ${VAR}";
+        assert!(is_templated(reversed));
+
+        let nested = "${{VAR}";
+        assert!(!is_templated(nested));
+    }
+
+    #[test]
+    fn posix_subst() {
+        let vars = [("A", "1"), ("A_B", "2"), ("_c", "3")];
+        let out = substitute_posix("$A.$A_B-$A_Bx $_c$A ${A}é$é $", vars.iter().copied()).unwrap();
+        assert_eq!(out, "1.2-$A_Bx 31 1é$é $");
+
+        let out = substitute_posix("$$A $${A} $$$A", vars.iter().copied()).unwrap();
+        assert_eq!(out, "$A ${A} $$A");
+    }
+
+    #[test]
+    fn extracted_variables() {
+        let found = extract_variables("${a} $${b} ${c:-x}\n${a}").unwrap();
+        let found: Vec<_> = found.iter().map(|p| (p.name(), p.span())).collect();
+        assert_eq!(found, vec![("a", 0..4), ("c", 11..18), ("a", 19..23)]);
+        assert!(extract_variables("no placeholders, $ or {}")
+            .unwrap()
+            .is_empty());
+
+        for malformed in &["${a", "x\n ${{a}", "${a${b}}", "${a} ${"] {
+            let err = extract_variables(malformed).unwrap_err();
+            assert!(
+                matches!(err, Error::MalformedPlaceholder { .. }),
+                "{}",
+                malformed
+            );
+        }
+        match extract_variables("ok ${a}\n  ${b ${c}").unwrap_err() {
+            Error::MalformedPlaceholder { position } => {
+                assert_eq!((position.line, position.column), (2, 3));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn template_check_with() {
+        let dollar = Substitutor::new().syntax(Syntax::Dollar);
+        assert!(is_templated_with("foo $VAR", &dollar));
+        assert!(!is_templated_with("foo $1", &dollar));
+
+        let braces = Substitutor::new().syntax(Syntax::DoubleBraces);
+        assert!(is_templated_with("{{ VAR }}", &braces));
+        assert!(!is_templated_with("${VAR} {VAR}", &braces));
+
+        let backslash = Substitutor::new().escape(Escape::Backslash);
+        assert!(!is_templated_with(r"\${VAR}", &backslash));
+        assert!(is_templated_with("$${VAR}", &backslash));
+        assert!(!is_templated_with("$${VAR}", &Substitutor::new()));
+    }
+
+    /// Deterministic pseudo-random template generator for property tests.
+    fn generate_templates(count: usize) -> Vec<String> {
+        const TOKENS: &[&str] = &["$", "{", "}", "${", ":-", "A", "B", "x", " ", "é", "\n"];
+        let mut state: u32 = 0x2545_f491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+        (0..count)
+            .map(|_| {
+                let len = next() % 16;
+                (0..len).map(|_| TOKENS[next() % TOKENS.len()]).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn guarantees() {
+        let pairs = [("A", "${B}"), ("B", "$"), ("x", "")];
+        let to_owned = |(k, v): &(&str, &str)| (k.to_string(), v.to_string());
+        let env: HashMap<_, _> = pairs.iter().map(to_owned).collect();
+        let reordered: HashMap<_, _> = pairs.iter().rev().map(to_owned).collect();
+
+        for template in generate_templates(2000) {
+            let escapes = template.contains("$${");
+            if !escapes && iter_placeholders(&template).all(|p| p.default().is_none()) {
+                let out = substitute(template.as_str(), &HashMap::new()).unwrap();
+                assert_eq!(out, template);
+            }
+
+            let out = substitute(template.as_str(), &env).unwrap();
+            if !escapes && !is_templated(&template) {
+                assert_eq!(out, template);
+            }
+
+            assert_eq!(substitute(escape(&template), &env).unwrap(), template);
+
+            // Reference expansion, placeholder by placeholder.
+            let unescape = |literal: &str| literal.replace("$${", "${");
+            let mut expected = String::new();
+            let mut last = 0;
+            for p in iter_placeholders(&template) {
+                expected.push_str(&unescape(&template[last..p.span().start]));
+                match (env.get(p.name()), p.default()) {
+                    (Some(v), _) => expected.push_str(v),
+                    (None, Some(default)) => expected.push_str(default),
+                    (None, None) => expected.push_str(&template[p.span()]),
+                }
+                last = p.span().end;
+            }
+            expected.push_str(&unescape(&template[last..]));
+            assert_eq!(out, expected, "template {:?}", template);
+
+            // A map with different insertion order gives the same output.
+            assert_eq!(substitute(template.as_str(), &reordered).unwrap(), out);
+        }
+    }
+
+    #[test]
+    fn render_modes() {
+        let pairs = [("A", "${B}"), ("B", "{{A}}"), ("x", "$A")];
+        let to_owned = |(k, v): &(&str, &str)| (k.to_string(), v.to_string());
+        let env: HashMap<_, _> = pairs.iter().map(to_owned).collect();
+        let reordered: HashMap<_, _> = pairs.iter().rev().map(to_owned).collect();
+        let engines = [
+            Substitutor::new(),
+            Substitutor::new().syntax(Syntax::Dollar),
+            Substitutor::new().syntax(Syntax::DoubleBraces),
+            Substitutor::new().escape(Escape::Backslash),
+            Substitutor::new().missing(Missing::Empty),
+        ];
+
+        // Output only depends on the template and the variables, whatever
+        // the rendering mode.
+        for template in generate_templates(500) {
+            for engine in &engines {
+                let out = engine.render(&template, &env).unwrap();
+                assert_eq!(engine.render(&template, &reordered).unwrap(), out);
+                let compiled = engine.compile(&template).unwrap();
+                assert_eq!(compiled.render(&env).unwrap(), out);
+
+                let reader = std::io::BufReader::with_capacity(3, template.as_bytes());
+                let mut streamed = Vec::new();
+                engine.render_stream(reader, &mut streamed, &env).unwrap();
+                assert_eq!(streamed, out.as_bytes(), "{:?} with {:?}", template, engine);
+            }
+        }
     }
 
     #[test]
@@ -163,9 +810,71 @@ mod tests {
         let mut env = HashMap::new();
         env.insert("${VAR}".to_string(), "var".to_string());
 
-        substitute(template, &env).unwrap_err();
+        let err = substitute(template, &env).unwrap_err();
+        match err {
+            Error::InvalidKey { name, character } => {
+                assert_eq!((name.as_str(), character), ("${VAR}", '$'));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
 
         let mut env = HashMap::new();
         env.insert("VAR".to_string(), "${VAR}".to_string());
+        let out = substitute(template, &env).unwrap();
+        assert_eq!(out, "foo ${VAR} bar");
+    }
+
+    #[test]
+    fn braces_in_values() {
+        let template = "VSCODE_NLS_CONFIG=${CONFIG}";
+        let mut env = HashMap::new();
+        env.insert("CONFIG".to_string(), r#"{"locale":"en-us"}"#.to_string());
+        validate_vars(&env).unwrap();
+
+        let out = substitute(template, &env).unwrap();
+        assert_eq!(out, r#"VSCODE_NLS_CONFIG={"locale":"en-us"}"#);
+    }
+
+    #[test]
+    fn closest_names() {
+        let names = ["DB_HOST", "DB_PORT", "USER"];
+        assert_eq!(closest_match("DB_HSOT", names), Some("DB_HOST"));
+        assert_eq!(closest_match("db_port", names), Some("DB_PORT"));
+        assert_eq!(closest_match("USR", names), Some("USER"));
+        assert_eq!(closest_match("PASSWORD", names), None);
+    }
+
+    #[test]
+    fn key_collisions() {
+        let mut env = HashMap::new();
+        env.insert("HOST".to_string(), "a".to_string());
+        env.insert("PORT".to_string(), "b".to_string());
+        validate_key_collisions(&env).unwrap();
+
+        env.insert("host".to_string(), "c".to_string());
+        env.insert(" PORT".to_string(), "d".to_string());
+        let err = validate_key_collisions(&env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: variable keys differing only by case or whitespace: 'HOST', 'host'; ' PORT', 'PORT'"
+        );
+    }
+
+    #[test]
+    fn layer_collisions() {
+        let layer = |keys: &[&str]| -> HashMap<String, String> {
+            keys.iter()
+                .map(|k| (k.to_string(), String::new()))
+                .collect()
+        };
+        let (first, second, third) = (layer(&["A", "B"]), layer(&["C", "a"]), layer(&["B", "C"]));
+        validate_layer_collisions(&[]).unwrap();
+        validate_layer_collisions(&[&first, &second]).unwrap();
+
+        let err = validate_layer_collisions(&[&first, &second, &third]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: variable keys defined in multiple layers: 'B' (layers 0, 2); 'C' (layers 1, 2)"
+        );
     }
 }
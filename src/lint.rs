@@ -0,0 +1,555 @@
+//! Template linting.
+//!
+//! This module checks templates against a configurable set of rules,
+//! reporting structured findings instead of failing on the first issue.
+//! It is meant to be run over template corpora, e.g. as a CI gate.
+//!
+//! ```rust
+//! use envsubst::lint::{Linter, Rule};
+//!
+//! let linter = Linter::new().known_variables(["HOST", "PORT"]);
+//! let findings = linter.lint("${HOST}:${Port}");
+//! assert_eq!(findings.len(), 2);
+//! assert_eq!(findings[0].rule, Rule::UndefinedVariable);
+//! assert_eq!(findings[1].rule, Rule::InconsistentCasing);
+//! ```
+
+use crate::placeholder::{Escape, Scanner, Syntax};
+use crate::report::deprecation_warning;
+use crate::{closest_match, is_identifier, iter_placeholders, Placeholder};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+
+/// Severity of a lint finding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Suspicious, but possibly intended.
+    Warning,
+    /// Almost certainly a mistake.
+    Error,
+}
+
+/// Lint rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    /// A placeholder references a variable outside of the known set.
+    UndefinedVariable,
+    /// The same variable is referenced with different casing.
+    InconsistentCasing,
+    /// A literal `$` appears outside of any placeholder.
+    StrayDollar,
+    /// A placeholder references a deprecated variable.
+    DeprecatedVariable,
+    /// A placeholder name is not a plain identifier (`[A-Za-z_][A-Za-z0-9_]*`).
+    ///
+    /// This usually means that `${` appeared by accident, e.g. in data.
+    InvalidIdentifier,
+    /// Text looks like a mistyped placeholder, e.g. `$ {VAR}` or `$(VAR)`.
+    NearMiss,
+    /// The same variable is referenced with different defaults or operators,
+    /// e.g. `${PORT:-80}` and `${PORT:-8080}`.
+    ///
+    /// Placeholders without any default nor operator are not compared.
+    InconsistentDefault,
+    /// Rendered output still contains placeholder-like text, in any syntax.
+    ///
+    /// This is only checked by [`Linter::lint_output`].
+    Leftover,
+}
+
+impl Rule {
+    /// Severity used when not overridden in the linter.
+    pub fn default_severity(self) -> Severity {
+        match self {
+            Rule::UndefinedVariable | Rule::InvalidIdentifier => Severity::Error,
+            Rule::InconsistentCasing
+            | Rule::StrayDollar
+            | Rule::DeprecatedVariable
+            | Rule::NearMiss
+            | Rule::InconsistentDefault
+            | Rule::Leftover => Severity::Warning,
+        }
+    }
+}
+
+/// A single issue found in a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// Rule which produced this finding.
+    pub rule: Rule,
+    /// Severity of this finding.
+    pub severity: Severity,
+    /// Byte range within the template this finding refers to.
+    pub span: Range<usize>,
+    /// Human-readable description.
+    pub message: String,
+}
+
+/// Configurable template linter.
+#[derive(Clone, Debug, Default)]
+pub struct Linter {
+    known: Option<BTreeSet<String>>,
+    deprecated: BTreeMap<String, String>,
+    levels: HashMap<Rule, Option<Severity>>,
+}
+
+impl Linter {
+    /// Create a linter with all rules enabled at their default severity.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the known variables, enabling the `UndefinedVariable` rule.
+    pub fn known_variables<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.known = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Mark variable `name` as deprecated in favor of `replacement`.
+    ///
+    /// Deprecated variables are not reported as undefined.
+    pub fn deprecated<S, R>(mut self, name: S, replacement: R) -> Self
+    where
+        S: Into<String>,
+        R: Into<String>,
+    {
+        self.deprecated.insert(name.into(), replacement.into());
+        self
+    }
+
+    /// Override the severity reported for `rule`.
+    pub fn severity(mut self, rule: Rule, severity: Severity) -> Self {
+        self.levels.insert(rule, Some(severity));
+        self
+    }
+
+    /// Disable `rule` entirely.
+    pub fn disable(mut self, rule: Rule) -> Self {
+        self.levels.insert(rule, None);
+        self
+    }
+
+    /// Check `template`, returning all findings ordered by position.
+    pub fn lint(&self, template: &str) -> Vec<Finding> {
+        let placeholders: Vec<_> = iter_placeholders(template).collect();
+        let mut findings = Vec::new();
+
+        self.check_identifiers(&placeholders, &mut findings);
+        self.check_undefined(&placeholders, &mut findings);
+        self.check_deprecated(&placeholders, &mut findings);
+        self.check_casing(&placeholders, &mut findings);
+        self.check_defaults(template, &placeholders, &mut findings);
+        self.check_stray_dollar(template, &mut findings);
+        self.check_near_misses(template, &mut findings);
+
+        findings.sort_by_key(|f| f.span.start);
+        findings
+    }
+
+    /// Check rendered `output` for leftover placeholders, ordered by position.
+    ///
+    /// This looks for `${VAR}`, `{{VAR}}`, `$(VAR)` and `%VAR%` patterns, as
+    /// well as unterminated `${`. These usually mean that a template was
+    /// rendered with the wrong syntax, so that nothing was substituted.
+    ///
+    /// ```rust
+    /// use envsubst::lint::{Linter, Rule};
+    ///
+    /// let findings = Linter::new().lint_output("host={{ HOST }} port=%PORT%");
+    /// assert_eq!(findings.len(), 2);
+    /// assert!(findings.iter().all(|f| f.rule == Rule::Leftover));
+    /// ```
+    pub fn lint_output(&self, output: &str) -> Vec<Finding> {
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        for syntax in &[Syntax::Braced, Syntax::DoubleBraces] {
+            spans.extend(Scanner::new(output, *syntax, Escape::None).map(|t| t.span()));
+        }
+        let bytes = output.as_bytes();
+        for (pos, &b) in bytes.iter().enumerate() {
+            let (open_len, close) = match b {
+                b'$' if bytes.get(pos + 1) == Some(&b'(') => (2, b')'),
+                b'%' => (1, b'%'),
+                _ => continue,
+            };
+            let name_len = ident_len(&output[pos + open_len..]);
+            let end = pos + open_len + name_len;
+            if name_len > 0 && bytes.get(end) == Some(&close) {
+                spans.push(pos..end + 1);
+            }
+        }
+        for (pos, _) in output.match_indices("${") {
+            if !spans.iter().any(|s| s.start < pos + 2 && pos < s.end) {
+                spans.push(pos..pos + 2);
+            }
+        }
+
+        // Keep the longest match among overlapping ones.
+        spans.sort_by_key(|span| (span.start, std::cmp::Reverse(span.end)));
+        let mut findings = Vec::new();
+        let mut covered = 0;
+        for span in spans {
+            if span.start < covered {
+                continue;
+            }
+            covered = span.end;
+            let message = format!(
+                "'{}' looks like a placeholder left unsubstituted",
+                &output[span.clone()]
+            );
+            self.push(&mut findings, Rule::Leftover, span, message);
+        }
+        findings
+    }
+
+    fn check_identifiers(&self, placeholders: &[Placeholder], findings: &mut Vec<Finding>) {
+        for p in placeholders {
+            if !is_identifier(p.name()) {
+                let message = format!("placeholder name '{}' is not an identifier", p.name());
+                self.push(findings, Rule::InvalidIdentifier, p.span(), message);
+            }
+        }
+    }
+
+    fn check_undefined(&self, placeholders: &[Placeholder], findings: &mut Vec<Finding>) {
+        let known = match &self.known {
+            Some(known) => known,
+            None => return,
+        };
+        for p in placeholders {
+            if !known.contains(p.name()) && !self.deprecated.contains_key(p.name()) {
+                let mut message = format!("variable '{}' is not defined", p.name());
+                if let Some(hint) = closest_match(p.name(), known.iter().map(String::as_str)) {
+                    message.push_str(&format!(", did you mean '{}'?", hint));
+                }
+                self.push(findings, Rule::UndefinedVariable, p.span(), message);
+            }
+        }
+    }
+
+    fn check_deprecated(&self, placeholders: &[Placeholder], findings: &mut Vec<Finding>) {
+        for p in placeholders {
+            if let Some(replacement) = self.deprecated.get(p.name()) {
+                let message = deprecation_warning(p.name(), replacement);
+                self.push(findings, Rule::DeprecatedVariable, p.span(), message);
+            }
+        }
+    }
+
+    fn check_casing(&self, placeholders: &[Placeholder], findings: &mut Vec<Finding>) {
+        // Known names take precedence as canonical spelling, then first use.
+        let mut canonical: BTreeMap<String, &str> = BTreeMap::new();
+        for name in self.known.iter().flatten() {
+            canonical.entry(name.to_lowercase()).or_insert(name);
+        }
+        for p in placeholders {
+            canonical.entry(p.name().to_lowercase()).or_insert(p.name());
+        }
+
+        for p in placeholders {
+            let expected = canonical[&p.name().to_lowercase()];
+            if p.name() != expected {
+                let message = format!(
+                    "variable '{}' differs only by case from '{}'",
+                    p.name(),
+                    expected
+                );
+                self.push(findings, Rule::InconsistentCasing, p.span(), message);
+            }
+        }
+    }
+
+    fn check_defaults(
+        &self,
+        template: &str,
+        placeholders: &[Placeholder],
+        findings: &mut Vec<Finding>,
+    ) {
+        // First placeholder with an operator, for each variable.
+        let mut first: HashMap<&str, &Placeholder> = HashMap::new();
+        for p in placeholders {
+            if operator(p).is_none() {
+                continue;
+            }
+            let reference = *first.entry(p.name()).or_insert(p);
+            if operator(p) != operator(reference) {
+                let message = format!(
+                    "variable '{}' is used as '{}', but as '{}' earlier",
+                    p.name(),
+                    &template[p.span()],
+                    &template[reference.span()]
+                );
+                self.push(findings, Rule::InconsistentDefault, p.span(), message);
+            }
+        }
+    }
+
+    fn check_stray_dollar(&self, template: &str, findings: &mut Vec<Finding>) {
+        // Both placeholders and escape sequences account for a `$`.
+        let mut spans = Scanner::new(template, Syntax::Braced, Escape::DoubleDollar)
+            .map(|token| token.span())
+            .peekable();
+        for (pos, _) in template.match_indices('$') {
+            while spans.peek().map_or(false, |s| s.end <= pos) {
+                spans.next();
+            }
+            if spans.peek().map_or(false, |s| s.contains(&pos)) {
+                continue;
+            }
+            let message = "literal '$' outside of a placeholder".to_string();
+            self.push(findings, Rule::StrayDollar, pos..pos + 1, message);
+        }
+    }
+
+    fn check_near_misses(&self, template: &str, findings: &mut Vec<Finding>) {
+        let bytes = template.as_bytes();
+        let mut covered = 0;
+        for (pos, &b) in bytes.iter().enumerate() {
+            if pos < covered {
+                continue;
+            }
+            let candidate = match b {
+                b'$' => near_miss_dollar(&template[pos..]),
+                b'{' if pos == 0 || !matches!(bytes[pos - 1], b'$' | b'{') => {
+                    near_miss_brace(&template[pos..])
+                }
+                _ => None,
+            };
+            if let Some((len, name)) = candidate {
+                let span = pos..pos + len;
+                covered = span.end;
+                let message = format!(
+                    "'{}' looks like a mistyped placeholder, did you mean '${{{}}}'?",
+                    &template[span.clone()],
+                    name
+                );
+                self.push(findings, Rule::NearMiss, span, message);
+            }
+        }
+    }
+
+    fn push(&self, findings: &mut Vec<Finding>, rule: Rule, span: Range<usize>, message: String) {
+        let severity = match self.levels.get(&rule) {
+            Some(level) => *level,
+            None => Some(rule.default_severity()),
+        };
+        if let Some(severity) = severity {
+            findings.push(Finding {
+                rule,
+                severity,
+                span,
+                message,
+            });
+        }
+    }
+}
+
+/// Length of the identifier at the start of `input`.
+fn ident_len(input: &str) -> usize {
+    match input.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+        Some(len) => len,
+        None => input.len(),
+    }
+}
+
+/// Detect `$ {VAR}`, `$(VAR)` and `${VAR )` at the start of `input`.
+///
+/// This returns the length of the suspicious token and the variable name.
+fn near_miss_dollar(input: &str) -> Option<(usize, &str)> {
+    let rest = &input[1..];
+    let trimmed = rest.trim_start_matches(|c| c == ' ' || c == '\t');
+    let spaces = rest.len() - trimmed.len();
+
+    let (open, close) = match trimmed.as_bytes().first() {
+        Some(b'{') if spaces > 0 => (1 + spaces, b'}'),
+        Some(b'(') if spaces == 0 && !trimmed.starts_with("((") => (1, b')'),
+        Some(b'{') => {
+            // Braced placeholder with a mismatched closing delimiter.
+            let name_len = ident_len(&rest[1..]);
+            let after = &rest[1 + name_len..];
+            let after_trimmed = after.trim_start_matches(' ');
+            let end = 2 + name_len + (after.len() - after_trimmed.len());
+            return match after_trimmed.as_bytes().first() {
+                Some(b')') | Some(b']') if name_len > 0 => Some((end + 1, &rest[1..1 + name_len])),
+                _ => None,
+            };
+        }
+        _ => return None,
+    };
+
+    let name_start = open + 1;
+    let name_len = ident_len(&input[name_start..]);
+    let end = name_start + name_len;
+    if name_len > 0 && input.as_bytes().get(end) == Some(&close) {
+        return Some((end + 1, &input[name_start..end]));
+    }
+    None
+}
+
+/// Detect `{VAR}` (missing the leading `$`) at the start of `input`.
+fn near_miss_brace(input: &str) -> Option<(usize, &str)> {
+    let name_len = ident_len(&input[1..]);
+    let end = 1 + name_len;
+    let bytes = input.as_bytes();
+    if name_len > 0 && bytes.get(end) == Some(&b'}') && bytes.get(end + 1) != Some(&b'}') {
+        return Some((end + 1, &input[1..end]));
+    }
+    None
+}
+
+/// Operator and its argument in placeholder `p`, e.g. `(":-", "80")`.
+fn operator<'a>(p: &Placeholder<'a>) -> Option<(&'static str, &'a str)> {
+    match (p.default(), p.required()) {
+        (Some(default), _) => Some((":-", default)),
+        (None, Some(message)) => Some((":?", message)),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lint_rules() {
+        let template = "$ ${HOST}:${PORT} ${host} ${Port} costs $5";
+        let findings = Linter::new().lint(template);
+        let rules: Vec<_> = findings.iter().map(|f| (f.rule, f.span.start)).collect();
+        assert_eq!(
+            rules,
+            vec![
+                (Rule::StrayDollar, 0),
+                (Rule::InconsistentCasing, 18),
+                (Rule::InconsistentCasing, 26),
+                (Rule::StrayDollar, 40),
+            ]
+        );
+        assert_eq!(findings[1].severity, Severity::Warning);
+        assert_eq!(
+            findings[1].message,
+            "variable 'host' differs only by case from 'HOST'"
+        );
+
+        let findings = Linter::new().known_variables(["PORT"]).lint(template);
+        let undefined: Vec<_> = findings
+            .iter()
+            .filter(|f| f.rule == Rule::UndefinedVariable)
+            .map(|f| &template[f.span.clone()])
+            .collect();
+        assert_eq!(undefined, vec!["${HOST}", "${host}", "${Port}"]);
+
+        let findings = Linter::new()
+            .known_variables(["DB_HOST"])
+            .lint("${DB_HSOT}");
+        assert_eq!(
+            findings[0].message,
+            "variable 'DB_HSOT' is not defined, did you mean 'DB_HOST'?"
+        );
+    }
+
+    #[test]
+    fn lint_deprecated() {
+        let linter = Linter::new()
+            .known_variables(["DB_HOST"])
+            .deprecated("DATABASE_HOST", "DB_HOST");
+        let findings = linter.lint("${DATABASE_HOST} ${DB_HOST}");
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, Rule::DeprecatedVariable);
+        assert_eq!(findings[0].span, 0..16);
+        assert_eq!(
+            findings[0].message,
+            "variable 'DATABASE_HOST' is deprecated, use 'DB_HOST' instead"
+        );
+    }
+
+    #[test]
+    fn lint_identifiers() {
+        let template = r#"${OK_1} ${"key": 1} ${} ${1st} ${with space}"#;
+        let findings = Linter::new().lint(template);
+        let invalid: Vec<_> = findings
+            .iter()
+            .filter(|f| f.rule == Rule::InvalidIdentifier)
+            .map(|f| &template[f.span.clone()])
+            .collect();
+        assert_eq!(
+            invalid,
+            vec![r#"${"key": 1}"#, "${}", "${1st}", "${with space}"]
+        );
+        assert!(findings.iter().all(|f| f.severity == Severity::Error));
+    }
+
+    #[test]
+    fn lint_near_misses() {
+        let template = "$ {A} ${B ) $(C) {D} ${E} $((1+2)) {{F}} {\"json\": 1}";
+        let findings = Linter::new().disable(Rule::StrayDollar).lint(template);
+        let near: Vec<_> = findings
+            .iter()
+            .filter(|f| f.rule == Rule::NearMiss)
+            .map(|f| &template[f.span.clone()])
+            .collect();
+        assert_eq!(near, vec!["$ {A}", "${B )", "$(C)", "{D}"]);
+        assert_eq!(
+            findings[0].message,
+            "'$ {A}' looks like a mistyped placeholder, did you mean '${A}'?"
+        );
+    }
+
+    #[test]
+    fn lint_leftovers() {
+        let output = "a=${A} b={{ B }} c=$(C) d=%D% e=${ f=${{F}} 100% $(( 1 )) %% $x";
+        let findings = Linter::new().lint_output(output);
+        let found: Vec<_> = findings.iter().map(|f| &output[f.span.clone()]).collect();
+        assert_eq!(found, vec!["${A}", "{{ B }}", "$(C)", "%D%", "${", "{{F}}"]);
+        assert_eq!(
+            findings[0].message,
+            "'${A}' looks like a placeholder left unsubstituted"
+        );
+        assert!(Linter::new().lint_output("rendered: 100%").is_empty());
+
+        let linter = Linter::new().disable(Rule::Leftover);
+        assert!(linter.lint_output(output).is_empty());
+        assert!(Linter::new().lint("%D%").is_empty());
+    }
+
+    #[test]
+    fn lint_defaults() {
+        let template = "${PORT:-80} ${PORT} ${PORT:-8080} ${HOST:?} ${PORT:-80} ${HOST:-x}";
+        let findings = Linter::new().lint(template);
+        let found: Vec<_> = findings
+            .iter()
+            .map(|f| (f.rule, &template[f.span.clone()]))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Rule::InconsistentDefault, "${PORT:-8080}"),
+                (Rule::InconsistentDefault, "${HOST:-x}"),
+            ]
+        );
+        assert_eq!(
+            findings[0].message,
+            "variable 'PORT' is used as '${PORT:-8080}', but as '${PORT:-80}' earlier"
+        );
+    }
+
+    #[test]
+    fn lint_config() {
+        let findings = Linter::new().lint("$${ESCAPED} $$");
+        let spans: Vec<_> = findings.iter().map(|f| f.span.clone()).collect();
+        assert_eq!(spans, vec![12..13, 13..14]);
+
+        let template = "${A} $";
+        let linter = Linter::new()
+            .known_variables(Vec::<String>::new())
+            .severity(Rule::UndefinedVariable, Severity::Warning)
+            .disable(Rule::StrayDollar);
+        let findings = linter.lint(template);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, Rule::UndefinedVariable);
+        assert_eq!(findings[0].severity, Severity::Warning);
+    }
+}
@@ -0,0 +1,366 @@
+//! Placeholder scanning.
+
+use std::ops::Range;
+
+/// Placeholder syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Syntax {
+    /// Braces-delimited variables, e.g. `${foo}`.
+    Braced,
+    /// Both braced and bare variables, e.g. `${foo}` and `$foo`, like the
+    /// GNU utility. Bare names follow POSIX identifier rules.
+    Dollar,
+    /// Double-braces-delimited variables, e.g. `{{foo}}` or `{{ foo }}`.
+    DoubleBraces,
+}
+
+/// Escaping rules, to produce a literal placeholder in the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Escape {
+    /// No escaping.
+    None,
+    /// A doubled dollar sign, e.g. `$${foo}` renders as `${foo}`.
+    ///
+    /// This only applies to `$`-based syntaxes.
+    DoubleDollar,
+    /// A leading backslash, e.g. `\${foo}` renders as `${foo}`.
+    Backslash,
+}
+
+/// A placeholder occurrence within a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placeholder<'a> {
+    name: &'a str,
+    default: Option<&'a str>,
+    required: Option<&'a str>,
+    span: Range<usize>,
+}
+
+impl<'a> Placeholder<'a> {
+    /// Variable name referenced by this placeholder.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Default value, for placeholders of the form `${foo:-default}`.
+    pub fn default(&self) -> Option<&'a str> {
+        self.default
+    }
+
+    /// Error message, for placeholders of the form `${foo:?message}`.
+    ///
+    /// Such placeholders fail the substitution if `foo` is undefined or
+    /// empty, with the given message (which may be empty).
+    pub fn required(&self) -> Option<&'a str> {
+        self.required
+    }
+
+    /// Byte range of the whole placeholder (e.g. `${foo}`) within the input.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+/// Token found while scanning a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// A placeholder to substitute.
+    Placeholder(Placeholder<'a>),
+    /// An escape sequence, to be rendered as `literal`.
+    Escape {
+        span: Range<usize>,
+        literal: &'static str,
+    },
+}
+
+impl Token<'_> {
+    /// Byte range of this token within the input.
+    pub(crate) fn span(&self) -> Range<usize> {
+        match self {
+            Token::Placeholder(p) => p.span(),
+            Token::Escape { span, .. } => span.clone(),
+        }
+    }
+}
+
+/// Template scanner, yielding placeholders and escapes for a given syntax.
+#[derive(Clone, Debug)]
+pub(crate) struct Scanner<'a> {
+    input: &'a str,
+    pos: usize,
+    syntax: Syntax,
+    escape: Escape,
+}
+
+impl<'a> Scanner<'a> {
+    pub(crate) fn new(input: &'a str, syntax: Syntax, escape: Escape) -> Self {
+        Self {
+            input,
+            pos: 0,
+            syntax,
+            escape,
+        }
+    }
+
+    /// Whether `syntax` placeholders start with a `$`.
+    fn dollar_based(&self) -> bool {
+        matches!(self.syntax, Syntax::Braced | Syntax::Dollar)
+    }
+
+    /// Whether a placeholder may start at byte `pos`.
+    fn opens_at(&self, pos: usize) -> bool {
+        let rest = &self.input.as_bytes()[pos..];
+        match self.syntax {
+            Syntax::Braced => rest.starts_with(b"${"),
+            Syntax::Dollar => {
+                rest.starts_with(b"${")
+                    || (rest.first() == Some(&b'$') && rest.get(1).map_or(false, is_ident_start))
+            }
+            Syntax::DoubleBraces => rest.starts_with(b"{{"),
+        }
+    }
+
+    /// Try to recognize a token starting at byte `start`.
+    fn token_at(&self, start: usize) -> Option<Token<'a>> {
+        let bytes = self.input.as_bytes();
+        match bytes[start] {
+            b'\\' if self.escape == Escape::Backslash && self.opens_at(start + 1) => {
+                let literal = if self.dollar_based() { "$" } else { "{{" };
+                let span = start..start + 1 + literal.len();
+                Some(Token::Escape { span, literal })
+            }
+            b'$' if self.escape == Escape::DoubleDollar
+                && self.dollar_based()
+                && bytes.get(start + 1) == Some(&b'$')
+                && self.opens_at(start + 1) =>
+            {
+                let span = start..start + 2;
+                Some(Token::Escape { span, literal: "$" })
+            }
+            b'$' if self.dollar_based() => self.dollar_at(start).map(Token::Placeholder),
+            b'{' if self.syntax == Syntax::DoubleBraces => {
+                self.double_braces_at(start).map(Token::Placeholder)
+            }
+            _ => None,
+        }
+    }
+
+    /// Recognize `${foo}` and (if enabled) `$foo` at byte `start`.
+    fn dollar_at(&self, start: usize) -> Option<Placeholder<'a>> {
+        let rest = &self.input[start + 1..];
+        if let Some(content) = rest.strip_prefix('{') {
+            let len = content.find(|c| c == '$' || c == '{' || c == '}')?;
+            if content.as_bytes()[len] != b'}' {
+                return None;
+            }
+            let (name, default, required) = split_operator(&content[..len]);
+            return Some(Placeholder {
+                name,
+                default,
+                required,
+                span: start..start + len + 3,
+            });
+        }
+
+        if self.syntax == Syntax::Dollar && rest.as_bytes().first().map_or(false, is_ident_start) {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            return Some(Placeholder {
+                name: &rest[..len],
+                default: None,
+                required: None,
+                span: start..start + len + 1,
+            });
+        }
+        None
+    }
+
+    /// Recognize `{{foo}}` at byte `start`.
+    fn double_braces_at(&self, start: usize) -> Option<Placeholder<'a>> {
+        let content = self.input[start..].strip_prefix("{{")?;
+        let len = content.find(|c| c == '{' || c == '}')?;
+        if !content[len..].starts_with("}}") {
+            return None;
+        }
+        let (name, default, required) = split_operator(content[..len].trim());
+        Some(Placeholder {
+            name,
+            default,
+            required,
+            span: start..start + len + 4,
+        })
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let rest = self.input.get(self.pos..)?;
+            let start = self.pos + rest.find(|c| c == '$' || c == '{' || c == '\\')?;
+            match self.token_at(start) {
+                Some(token) => {
+                    self.pos = token.span().end;
+                    return Some(token);
+                }
+                None => self.pos = start + 1,
+            }
+        }
+    }
+}
+
+/// Split placeholder content into a name, and either an optional default
+/// value (`:-`) or an optional error message (`:?`), whichever comes first.
+fn split_operator(content: &str) -> (&str, Option<&str>, Option<&str>) {
+    let operator = content
+        .match_indices(':')
+        .find(|(sep, _)| matches!(content.as_bytes().get(sep + 1), Some(b'-') | Some(b'?')));
+    match operator {
+        Some((sep, _)) if content.as_bytes()[sep + 1] == b'-' => {
+            (&content[..sep], Some(&content[sep + 2..]), None)
+        }
+        Some((sep, _)) => (&content[..sep], None, Some(&content[sep + 2..])),
+        None => (content, None, None),
+    }
+}
+
+fn is_ident_start(b: &u8) -> bool {
+    b.is_ascii_alphabetic() || *b == b'_'
+}
+
+/// Iterator over placeholders in a string, see [`iter_placeholders`].
+#[derive(Clone, Debug)]
+pub struct Placeholders<'a> {
+    scanner: Scanner<'a>,
+}
+
+impl<'a> Iterator for Placeholders<'a> {
+    type Item = Placeholder<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for token in &mut self.scanner {
+            if let Token::Placeholder(p) = token {
+                return Some(p);
+            }
+        }
+        None
+    }
+}
+
+/// Lazily iterate over all placeholders in a string.
+///
+/// This scans `input` for tokens of the form `${foo}` (or `${foo:-default}`),
+/// yielding each occurrence in order with its borrowed name and byte span,
+/// without any allocation. Escaped placeholders (e.g. `$${foo}`) are skipped.
+///
+/// ```rust
+/// let names: Vec<_> = envsubst::iter_placeholders("${a}/${b}.${a}")
+///     .map(|p| p.name())
+///     .collect();
+/// assert_eq!(names, vec!["a", "b", "a"]);
+/// ```
+pub fn iter_placeholders(input: &str) -> Placeholders<'_> {
+    Placeholders {
+        scanner: Scanner::new(input, Syntax::Braced, Escape::DoubleDollar),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_scan() {
+        let input = "foo ${VAR} bar ${OTHER}";
+        let found: Vec<_> = iter_placeholders(input).collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].name(), "VAR");
+        assert_eq!(found[0].span(), 4..10);
+        assert_eq!(found[1].name(), "OTHER");
+        assert_eq!(&input[found[1].span()], "${OTHER}");
+    }
+
+    #[test]
+    fn default_scan() {
+        let input = "${A:-fallback} ${B:-} ${C:-with spaces:-more} ${D}";
+        let found: Vec<_> = iter_placeholders(input)
+            .map(|p| (p.name(), p.default()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("A", Some("fallback")),
+                ("B", Some("")),
+                ("C", Some("with spaces:-more")),
+                ("D", None),
+            ]
+        );
+
+        let input = "${A:?must be set} ${B:?} ${C:-x:?y} ${D:?x:-y} ${E?}";
+        let found: Vec<_> = iter_placeholders(input)
+            .map(|p| (p.name(), p.default(), p.required()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("A", None, Some("must be set")),
+                ("B", None, Some("")),
+                ("C", Some("x:?y"), None),
+                ("D", None, Some("x:-y")),
+                ("E?", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_scan() {
+        let names = |input| -> Vec<&str> { iter_placeholders(input).map(|p| p.name()).collect() };
+
+        assert!(names("foo").is_empty());
+        assert!(names("foo ${ bar").is_empty());
+        assert!(names("} ${").is_empty());
+        assert_eq!(names("${a${b}"), vec!["b"]);
+        assert_eq!(names("${a{b} ${c}"), vec!["c"]);
+        assert_eq!(names("${${x}}"), vec!["x"]);
+        assert_eq!(names("${é}"), vec!["é"]);
+        assert_eq!(names("$${a} $$${b} ${c}"), vec!["c"]);
+    }
+
+    #[test]
+    fn syntax_scan() {
+        let scan = |input, syntax, escape| -> Vec<&str> {
+            Scanner::new(input, syntax, escape)
+                .map(|t| &input[t.span()])
+                .collect()
+        };
+
+        let input = "$A ${B} $1 $_c-d {{e}} {{ f:-x }} {{{g}}}";
+        assert_eq!(scan(input, Syntax::Braced, Escape::None), vec!["${B}"]);
+        assert_eq!(
+            scan(input, Syntax::Dollar, Escape::None),
+            vec!["$A", "${B}", "$_c"]
+        );
+        assert_eq!(
+            scan(input, Syntax::DoubleBraces, Escape::None),
+            vec!["{{e}}", "{{ f:-x }}", "{{g}}"]
+        );
+
+        let input = r"$${A} \${B} $$ $$$${C} \{{d}} \$5";
+        assert_eq!(
+            scan(input, Syntax::Braced, Escape::DoubleDollar),
+            vec!["$$", "${B}", "$$"]
+        );
+        assert_eq!(
+            scan(input, Syntax::Braced, Escape::Backslash),
+            vec!["${A}", r"\$", "${C}"]
+        );
+        assert_eq!(
+            scan(input, Syntax::DoubleBraces, Escape::Backslash),
+            vec![r"\{{"]
+        );
+    }
+}
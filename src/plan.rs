@@ -0,0 +1,65 @@
+//! Two-phase rendering, planning then committing.
+//!
+//! A [`RenderPlan`] holds a fully resolved rendering: all checks have been
+//! done and all values looked up, so that committing it cannot fail. This
+//! allows showing what a rendering would do, e.g. for approval, before
+//! generating the output.
+
+use std::ops::Range;
+
+/// Where the value of a planned placeholder comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Source {
+    /// A variable value, including from [`Substitutor::defaults`](crate::Substitutor::defaults).
+    Variable,
+    /// The default value of the placeholder.
+    Default,
+    /// Nothing, the placeholder is kept or emptied as configured.
+    Unresolved,
+}
+
+/// A placeholder resolved by a plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Substitution {
+    /// Variable name.
+    pub name: String,
+    /// Byte range of the placeholder in the template.
+    pub span: Range<usize>,
+    /// Text which replaces the placeholder in the output.
+    pub value: String,
+    /// Where `value` comes from.
+    pub source: Source,
+}
+
+/// A resolved rendering, see [`Template::plan`](crate::Template::plan).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderPlan {
+    pieces: Vec<String>,
+    substitutions: Vec<Substitution>,
+}
+
+impl RenderPlan {
+    /// Placeholders of the template, in order, with how they are resolved.
+    pub fn substitutions(&self) -> &[Substitution] {
+        &self.substitutions
+    }
+
+    /// Produce the output.
+    pub fn commit(self) -> String {
+        self.pieces.concat()
+    }
+
+    /// Add a piece of output, from a placeholder if `placeholder` is set.
+    pub(crate) fn push(&mut self, value: &str, placeholder: Option<(&str, Range<usize>, Source)>) {
+        self.pieces.push(value.to_string());
+        if let Some((name, span, source)) = placeholder {
+            self.substitutions.push(Substitution {
+                name: name.to_string(),
+                span,
+                value: value.to_string(),
+                source,
+            });
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Annotated template previews.
+//!
+//! A preview splits a template into literal text and placeholders, telling
+//! for each placeholder how it would be resolved against some variables,
+//! without failing on undefined ones. It can be inspected region by region,
+//! e.g. to highlight a template in a user interface, or printed in a marked
+//! or colored text form.
+//!
+//! ```rust
+//! use envsubst::Template;
+//!
+//! let mut context = std::collections::HashMap::new();
+//! context.insert("host".to_string(), "example.com".to_string());
+//!
+//! let template = Template::parse("${scheme:-https}://${host}/${path}").unwrap();
+//! let preview = template.preview(&context);
+//! assert_eq!(preview.marked(), "[scheme:-https]://[host=example.com]/[path?]");
+//! ```
+
+use std::ops::Range;
+
+/// A region of a previewed template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Region {
+    /// Literal text, with escape sequences already resolved.
+    Literal(String),
+    /// A placeholder with a value from the variables.
+    Resolved {
+        /// Variable name.
+        name: String,
+        /// Variable value, before filters and recursive expansion.
+        value: String,
+        /// Byte range of the placeholder in the template.
+        span: Range<usize>,
+    },
+    /// A placeholder falling back to its default value.
+    Defaulted {
+        /// Variable name.
+        name: String,
+        /// Default value of the placeholder.
+        value: String,
+        /// Byte range of the placeholder in the template.
+        span: Range<usize>,
+    },
+    /// A placeholder without any value.
+    Unresolved {
+        /// Variable name.
+        name: String,
+        /// Original text of the placeholder.
+        raw: String,
+        /// Byte range of the placeholder in the template.
+        span: Range<usize>,
+    },
+}
+
+/// Template preview, see [`Template::preview`](crate::Template::preview).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preview {
+    pub(crate) regions: Vec<Region>,
+}
+
+impl Preview {
+    /// Regions of the template, in order.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Text form, with placeholders marked between brackets.
+    ///
+    /// Placeholders are shown as `[name=value]` when resolved, as
+    /// `[name:-default]` when falling back to their default, and as
+    /// `[name?]` when unresolved.
+    pub fn marked(&self) -> String {
+        self.regions
+            .iter()
+            .map(|region| match region {
+                Region::Literal(text) => text.clone(),
+                Region::Resolved { name, value, .. } => format!("[{}={}]", name, value),
+                Region::Defaulted { name, value, .. } => format!("[{}:-{}]", name, value),
+                Region::Unresolved { name, .. } => format!("[{}?]", name),
+            })
+            .collect()
+    }
+
+    /// Text form for terminals, with values highlighted by ANSI colors.
+    ///
+    /// Resolved values are green, default values are yellow, and unresolved
+    /// placeholders are red.
+    pub fn ansi(&self) -> String {
+        const GREEN: &str = "\x1b[32m";
+        const YELLOW: &str = "\x1b[33m";
+        const RED: &str = "\x1b[31m";
+        const RESET: &str = "\x1b[0m";
+
+        self.regions
+            .iter()
+            .map(|region| match region {
+                Region::Literal(text) => text.clone(),
+                Region::Resolved { value, .. } => format!("{}{}{}", GREEN, value, RESET),
+                Region::Defaulted { value, .. } => format!("{}{}{}", YELLOW, value, RESET),
+                Region::Unresolved { raw, .. } => format!("{}{}{}", RED, raw, RESET),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Missing, Substitutor, Syntax};
+    use std::collections::HashMap;
+
+    #[test]
+    fn preview_regions() {
+        let mut env = HashMap::new();
+        env.insert("A".to_string(), "1".to_string());
+
+        let engine = Substitutor::new().missing(Missing::Error);
+        let template = engine.compile("$${A} ${A}${B:-2}${C}").unwrap();
+        let preview = template.preview(&env);
+        assert_eq!(
+            preview.regions(),
+            &[
+                Region::Literal("${A} ".to_string()),
+                Region::Resolved {
+                    name: "A".to_string(),
+                    value: "1".to_string(),
+                    span: 6..10,
+                },
+                Region::Defaulted {
+                    name: "B".to_string(),
+                    value: "2".to_string(),
+                    span: 10..17,
+                },
+                Region::Unresolved {
+                    name: "C".to_string(),
+                    raw: "${C}".to_string(),
+                    span: 17..21,
+                },
+            ]
+        );
+        assert_eq!(preview.marked(), "${A} [A=1][B:-2][C?]");
+        assert_eq!(
+            preview.ansi(),
+            "${A} \x1b[32m1\x1b[0m\x1b[33m2\x1b[0m\x1b[31m${C}\x1b[0m"
+        );
+
+        let engine = Substitutor::new().syntax(Syntax::DoubleBraces);
+        let preview = engine.compile("{{ A }}").unwrap().preview(&HashMap::new());
+        assert_eq!(preview.marked(), "[A?]");
+        assert_eq!(preview.ansi(), "\x1b[31m{{ A }}\x1b[0m");
+    }
+}
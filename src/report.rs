@@ -0,0 +1,155 @@
+//! Substitution reports.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Result of a substitution, with details about variables usage.
+///
+/// This is returned by [`substitute_with_report`](crate::substitute_with_report).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubstitutionReport {
+    /// Rendered output.
+    pub output: String,
+    /// Names of variables which were substituted with a value from the map.
+    pub substituted: BTreeSet<String>,
+    /// Names of all placeholders found in the template.
+    pub placeholders: BTreeSet<String>,
+}
+
+impl SubstitutionReport {
+    /// Version of the format produced by [`to_json`](Self::to_json).
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Variables defined in `variables` but never referenced by the template.
+    pub fn unused<'a>(&self, variables: &'a HashMap<String, String>) -> BTreeSet<&'a str> {
+        variables
+            .keys()
+            .filter(|k| !self.placeholders.contains(k.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Placeholders in the template which did not match any variable.
+    ///
+    /// This includes placeholders which were replaced by their default value.
+    pub fn unmatched(&self) -> BTreeSet<&str> {
+        self.placeholders
+            .difference(&self.substituted)
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Warnings for placeholders referencing deprecated variables, sorted
+    /// by name.
+    ///
+    /// `replacements` maps deprecated variable names to their replacement,
+    /// as registered with [`Linter::deprecated`](crate::lint::Linter::deprecated).
+    ///
+    /// ```rust
+    /// let mut replacements = std::collections::HashMap::new();
+    /// replacements.insert("DATABASE_HOST".to_string(), "DB_HOST".to_string());
+    ///
+    /// let context = std::collections::HashMap::new();
+    /// let report = envsubst::substitute_with_report("${DATABASE_HOST}", &context).unwrap();
+    /// assert_eq!(
+    ///     report.deprecated(&replacements),
+    ///     vec!["variable 'DATABASE_HOST' is deprecated, use 'DB_HOST' instead"]
+    /// );
+    /// ```
+    pub fn deprecated(&self, replacements: &HashMap<String, String>) -> Vec<String> {
+        self.placeholders
+            .iter()
+            .filter_map(|name| Some((name, replacements.get(name)?)))
+            .map(|(name, replacement)| deprecation_warning(name, replacement))
+            .collect()
+    }
+
+    /// Serialize this report as a JSON object, for archiving.
+    ///
+    /// The format is versioned by its `version` field: new fields may be
+    /// added within a version, but fields are only removed or changed in
+    /// meaning along with a version bump. Version 1 has these fields:
+    ///
+    ///  * `version`: format version, as a number.
+    ///  * `output`: rendered output, as a string.
+    ///  * `placeholders`: names of all placeholders, as a sorted array.
+    ///  * `substituted`: names of substituted variables, as a sorted array.
+    ///  * `unmatched`: names of placeholders without a matching variable,
+    ///    as a sorted array.
+    ///
+    /// ```rust
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("a".to_string(), "1".to_string());
+    ///
+    /// let report = envsubst::substitute_with_report("${a}${b}", &context).unwrap();
+    /// assert_eq!(
+    ///     report.to_json(),
+    ///     r#"{"version":1,"output":"1${b}","placeholders":["a","b"],"substituted":["a"],"unmatched":["b"]}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let names = |names: &mut dyn Iterator<Item = &str>| {
+            let quoted: Vec<_> = names.map(json_string).collect();
+            format!("[{}]", quoted.join(","))
+        };
+        format!(
+            r#"{{"version":{},"output":{},"placeholders":{},"substituted":{},"unmatched":{}}}"#,
+            Self::FORMAT_VERSION,
+            json_string(&self.output),
+            names(&mut self.placeholders.iter().map(String::as_str)),
+            names(&mut self.substituted.iter().map(String::as_str)),
+            names(&mut self.unmatched().into_iter()),
+        )
+    }
+}
+
+/// Warning message for a reference to deprecated variable `name`.
+pub(crate) fn deprecation_warning(name: &str, replacement: &str) -> String {
+    format!(
+        "variable '{}' is deprecated, use '{}' instead",
+        name, replacement
+    )
+}
+
+/// Quote `value` as a JSON string.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_format() {
+        assert_eq!(
+            SubstitutionReport::default().to_json(),
+            r#"{"version":1,"output":"","placeholders":[],"substituted":[],"unmatched":[]}"#
+        );
+
+        let report = SubstitutionReport {
+            output: "a \"quoted\"\\path\n\t\u{1}é".to_string(),
+            substituted: vec!["x".to_string()].into_iter().collect(),
+            placeholders: vec!["x".to_string(), "y\"".to_string()]
+                .into_iter()
+                .collect(),
+        };
+        assert_eq!(
+            report.to_json(),
+            r#"{"version":1,"output":"a \"quoted\"\\path\n\t\u0001é","placeholders":["x","y\""],"substituted":["x"],"unmatched":["y\""]}"#
+        );
+    }
+}
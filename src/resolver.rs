@@ -0,0 +1,184 @@
+//! Variables resolved on demand.
+//!
+//! A [`VariableResolver`] provides values by name when placeholders are
+//! rendered, instead of all upfront in a map. This allows fetching values
+//! lazily from the environment, a secret store, or computing them, and only
+//! for the variables which templates actually reference.
+//!
+//! ```rust
+//! use envsubst::resolver::Chain;
+//! use envsubst::Substitutor;
+//! use std::collections::HashMap;
+//!
+//! let mut overrides = HashMap::new();
+//! overrides.insert("HOST".to_string(), "example.com".to_string());
+//! let env = |name: &str| std::env::var(name).ok();
+//!
+//! let resolver = Chain::new().then(&overrides).then(&env);
+//! let out = Substitutor::new().render_resolver("https://${HOST}/", &resolver).unwrap();
+//! assert_eq!(out, "https://example.com/");
+//! ```
+
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+/// Source of variable values, queried by name.
+pub trait VariableResolver {
+    /// Value of variable `name`, if defined.
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>>;
+}
+
+impl<K, V, S> VariableResolver for HashMap<K, V, S>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+    S: BuildHasher,
+{
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get(name).map(|v| Cow::Borrowed(v.as_ref()))
+    }
+}
+
+impl<K, V> VariableResolver for BTreeMap<K, V>
+where
+    K: Borrow<str> + Ord,
+    V: AsRef<str>,
+{
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get(name).map(|v| Cow::Borrowed(v.as_ref()))
+    }
+}
+
+impl<F> VariableResolver for F
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self(name).map(Cow::Owned)
+    }
+}
+
+/// Pairs of names and values, the first pair with a name defining it.
+///
+/// This allows fixed variables in a `static` table, without allocating;
+/// for larger tables, see [`SortedSlice`].
+///
+/// ```rust
+/// static VARS: &[(&str, &str)] = &[("name", "envsubst"), ("os", "linux")];
+///
+/// let engine = envsubst::Substitutor::new();
+/// assert_eq!(engine.render_resolver("${name} on ${os}", VARS).unwrap(), "envsubst on linux");
+/// ```
+impl VariableResolver for [(&str, &str)] {
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| Cow::Borrowed(*v))
+    }
+}
+
+/// Pairs of names and values sorted by name, for lookups by binary search.
+///
+/// ```rust
+/// use envsubst::resolver::SortedSlice;
+///
+/// static VARS: SortedSlice = SortedSlice::new(&[("a", "1"), ("b", "2")]);
+/// let engine = envsubst::Substitutor::new();
+/// assert_eq!(engine.render_resolver("${b}${a}", &VARS).unwrap(), "21");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SortedSlice<'a> {
+    entries: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> SortedSlice<'a> {
+    /// Wrap `entries`, which must be sorted by name without duplicates.
+    ///
+    /// Unsorted entries are not detected here, but some of their variables
+    /// would not be found; see [`is_sorted`](Self::is_sorted) to check them,
+    /// e.g. in a test.
+    pub const fn new(entries: &'a [(&'a str, &'a str)]) -> Self {
+        Self { entries }
+    }
+
+    /// Whether entries are sorted by name, without duplicates.
+    pub fn is_sorted(&self) -> bool {
+        self.entries.windows(2).all(|pair| pair[0].0 < pair[1].0)
+    }
+}
+
+impl VariableResolver for SortedSlice<'_> {
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        let index = self.entries.binary_search_by_key(&name, |(k, _)| k).ok()?;
+        Some(Cow::Borrowed(self.entries[index].1))
+    }
+}
+
+/// Resolver trying several resolvers in order, until one defines a variable.
+#[derive(Default)]
+pub struct Chain<'a> {
+    resolvers: Vec<&'a dyn VariableResolver>,
+}
+
+impl<'a> Chain<'a> {
+    /// Create a chain without resolvers, resolving nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `resolver`, queried after the ones already in the chain.
+    pub fn then<R>(mut self, resolver: &'a R) -> Self
+    where
+        R: VariableResolver,
+    {
+        self.resolvers.push(resolver);
+        self
+    }
+}
+
+impl VariableResolver for Chain<'_> {
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.resolvers.iter().find_map(|r| r.resolve(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolvers() {
+        let mut first = HashMap::new();
+        first.insert("A", "1");
+        let mut second = BTreeMap::new();
+        second.insert("A".to_string(), "2".to_string());
+        second.insert("B".to_string(), "2".to_string());
+        let computed = |name: &str| match name {
+            "A" | "B" | "C" => Some(name.to_lowercase()),
+            _ => None,
+        };
+
+        let chain = Chain::new().then(&first).then(&second).then(&computed);
+        assert_eq!(chain.resolve("A").as_deref(), Some("1"));
+        assert_eq!(chain.resolve("B").as_deref(), Some("2"));
+        assert_eq!(chain.resolve("C").as_deref(), Some("c"));
+        assert_eq!(chain.resolve("D"), None);
+        assert_eq!(Chain::new().resolve("A"), None);
+    }
+
+    #[test]
+    fn slice_resolvers() {
+        let pairs: &[(&str, &str)] = &[("b", "1"), ("a", "2"), ("b", "3")];
+        assert_eq!(pairs.resolve("b").as_deref(), Some("1"));
+        assert_eq!(pairs.resolve("c"), None);
+
+        let sorted = SortedSlice::new(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        assert!(sorted.is_sorted());
+        assert_eq!(sorted.resolve("a").as_deref(), Some("1"));
+        assert_eq!(sorted.resolve("c").as_deref(), Some("3"));
+        assert_eq!(sorted.resolve("d"), None);
+        assert_eq!(sorted.resolve(""), None);
+        assert!(!SortedSlice::new(pairs).is_sorted());
+    }
+}
@@ -0,0 +1,208 @@
+//! Constraints on variable values.
+//!
+//! A [`Schema`] declares constraints on the values of some variables, e.g.
+//! an enumeration of allowed values or a numeric range, which are checked
+//! against a context before rendering. All violations are reported at once,
+//! so that a broken context can be fixed in one go.
+//!
+//! ```rust
+//! use envsubst::schema::Schema;
+//!
+//! let schema = Schema::new()
+//!     .one_of("env", ["dev", "staging", "prod"])
+//!     .range("port", 1..=65535);
+//!
+//! let mut context = std::collections::HashMap::new();
+//! context.insert("env".to_string(), "qa".to_string());
+//! context.insert("port".to_string(), "80".to_string());
+//! let err = schema.validate(&context).unwrap_err();
+//! assert_eq!(
+//!     err.to_string(),
+//!     "envsubst error: invalid variable values: 'env' is 'qa', not one of 'dev', 'prod', 'staging'"
+//! );
+//! ```
+
+use crate::Error;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// A constraint on the value of a variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Constraint {
+    /// The value must be one of these.
+    OneOf(BTreeSet<String>),
+    /// The value must be an integer within this range.
+    Range(RangeInclusive<i64>),
+}
+
+impl Constraint {
+    /// Whether `value` satisfies this constraint.
+    pub fn check(&self, value: &str) -> bool {
+        match self {
+            Constraint::OneOf(allowed) => allowed.contains(value),
+            Constraint::Range(range) => value.parse().map_or(false, |n| range.contains(&n)),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::OneOf(allowed) => {
+                let quoted: Vec<String> = allowed.iter().map(|v| format!("'{}'", v)).collect();
+                write!(f, "one of {}", quoted.join(", "))
+            }
+            Constraint::Range(range) => {
+                write!(f, "an integer from {} to {}", range.start(), range.end())
+            }
+        }
+    }
+}
+
+/// A value which does not satisfy a constraint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// Variable name.
+    pub name: String,
+    /// Variable value.
+    pub value: String,
+    /// Constraint which is not satisfied.
+    pub constraint: Constraint,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' is '{}', not {}",
+            self.name, self.value, self.constraint
+        )
+    }
+}
+
+/// Constraints on the values of variables.
+///
+/// Variables without constraints, and undefined variables, are always
+/// accepted; to require variables, see `${foo:?message}` placeholders.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schema {
+    constraints: BTreeMap<String, Vec<Constraint>>,
+}
+
+impl Schema {
+    /// Create a schema without constraints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constrain variable `name` with `constraint`, in addition to any
+    /// previous ones.
+    pub fn constraint<S>(mut self, name: S, constraint: Constraint) -> Self
+    where
+        S: Into<String>,
+    {
+        self.constraints
+            .entry(name.into())
+            .or_default()
+            .push(constraint);
+        self
+    }
+
+    /// Only allow `values` for variable `name`.
+    pub fn one_of<S, I, V>(self, name: S, values: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        let allowed = values.into_iter().map(Into::into).collect();
+        self.constraint(name, Constraint::OneOf(allowed))
+    }
+
+    /// Only allow integers within `range` for variable `name`.
+    pub fn range<S>(self, name: S, range: RangeInclusive<i64>) -> Self
+    where
+        S: Into<String>,
+    {
+        self.constraint(name, Constraint::Range(range))
+    }
+
+    /// All constraint violations in `variables`, sorted by name.
+    pub fn violations(&self, variables: &HashMap<String, String>) -> Vec<Violation> {
+        self.constraints
+            .iter()
+            .filter_map(|(name, constraints)| Some((name, variables.get(name)?, constraints)))
+            .flat_map(|(name, value, constraints)| {
+                constraints
+                    .iter()
+                    .filter(move |constraint| !constraint.check(value))
+                    .map(move |constraint| Violation {
+                        name: name.clone(),
+                        value: value.clone(),
+                        constraint: constraint.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Check `variables` against the constraints, failing with
+    /// [`Error::ConstraintViolation`] listing all violations.
+    pub fn validate(&self, variables: &HashMap<String, String>) -> Result<(), Error> {
+        let violations = self.violations(variables);
+        if !violations.is_empty() {
+            return Err(Error::ConstraintViolation { violations });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constraint_violations() {
+        let schema = Schema::new()
+            .one_of("env", vec!["dev", "prod"])
+            .range("port", 1..=65535)
+            .range("workers", -1..=64)
+            .constraint(
+                "workers",
+                Constraint::OneOf(["-1", "8", "600"].iter().map(|v| v.to_string()).collect()),
+            )
+            .range("undefined", 0..=0);
+
+        let mut context = HashMap::new();
+        context.insert("env".to_string(), "prod".to_string());
+        context.insert("port".to_string(), "8080".to_string());
+        context.insert("workers".to_string(), "-1".to_string());
+        schema.validate(&context).unwrap();
+
+        context.insert("env".to_string(), "Prod".to_string());
+        context.insert("port".to_string(), "80.5".to_string());
+        context.insert("workers".to_string(), "600".to_string());
+        let violations: Vec<_> = schema
+            .violations(&context)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                "'env' is 'Prod', not one of 'dev', 'prod'",
+                "'port' is '80.5', not an integer from 1 to 65535",
+                "'workers' is '600', not an integer from -1 to 64",
+            ]
+        );
+        match schema.validate(&context).unwrap_err() {
+            Error::ConstraintViolation { violations } => assert_eq!(violations.len(), 3),
+            err => panic!("unexpected error: {}", err),
+        }
+
+        context.insert("port".to_string(), "99999999999999999999".to_string());
+        assert!(!schema.violations(&context).is_empty());
+        schema.validate(&HashMap::new()).unwrap();
+    }
+}
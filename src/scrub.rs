@@ -0,0 +1,79 @@
+//! Scrubbing of sensitive values.
+
+use std::fmt::Display;
+
+/// Replacement text for scrubbed values.
+pub(crate) const MASK: &str = "***";
+
+/// Replace all occurrences of sensitive values in `input` with `***`.
+///
+/// This takes the same kind of variables collection as
+/// [`substitute`](crate::substitute), but only the values are relevant:
+/// pass the sensitive subset of a context to scrub rendered output or logs.
+/// Overlapping occurrences are masked together, so that no part of any
+/// secret is left in clear. Empty values are ignored.
+///
+/// ```rust
+/// let secrets = vec![("DB_PASSWORD", "hunter2")];
+/// let out = envsubst::scrub("connecting with password hunter2", secrets);
+/// assert_eq!(out, "connecting with password ***");
+/// ```
+pub fn scrub<S, I, K, V>(input: S, secrets: I) -> String
+where
+    S: AsRef<str>,
+    I: IntoIterator<Item = (K, V)>,
+    V: Display,
+{
+    let values: Vec<String> = secrets
+        .into_iter()
+        .map(|(_, v)| v.to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+
+    // All occurrences of all values, including overlapping ones.
+    let input = input.as_ref();
+    let mut ranges = Vec::new();
+    for value in &values {
+        let mut start = 0;
+        while let Some(found) = input[start..].find(value.as_str()) {
+            let begin = start + found;
+            ranges.push(begin..begin + value.len());
+            let next = input[begin..].chars().next().map_or(1, char::len_utf8);
+            start = begin + next;
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+
+    let mut output = String::with_capacity(input.len());
+    let mut pos = 0;
+    let mut ranges = ranges.into_iter().peekable();
+    while let Some(range) = ranges.next() {
+        let mut end = range.end;
+        while let Some(next) = ranges.peek().filter(|next| next.start < end) {
+            end = end.max(next.end);
+            ranges.next();
+        }
+        output.push_str(&input[pos..range.start]);
+        output.push_str(MASK);
+        pos = end;
+    }
+    output.push_str(&input[pos..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrub_values() {
+        let secrets = vec![("A", "abc"), ("B", "abcdef"), ("C", ""), ("D", "*")];
+        let out = scrub("abcdef abc ab * é", secrets);
+        assert_eq!(out, "*** *** ab *** é");
+
+        let secrets = [("A", "xab"), ("B", "abcdef")];
+        assert_eq!(scrub("xabcdef xab abcdefx", secrets), "*** *** ***x");
+        assert_eq!(scrub("aaaa abab", [("A", "aaa"), ("B", "ba")]), "*** a***b");
+        assert_eq!(scrub("éé", [("A", "é")]), "******");
+    }
+}
@@ -0,0 +1,178 @@
+//! Placeholders search across files.
+//!
+//! This is like `grep`, specialized for placeholders: it reports every
+//! placeholder occurrence in a file or a directory tree, according to the
+//! syntax and escaping rules of a [`Substitutor`]. Escaped placeholders
+//! (e.g. `$${foo}`) are not reported.
+//!
+//! ```rust,no_run
+//! use envsubst::Substitutor;
+//!
+//! for found in envsubst::search::search_tree("templates", &Substitutor::new()).unwrap() {
+//!     println!("{}:{}:{}: {}", found.path.display(), found.line, found.column, found.name);
+//! }
+//! ```
+
+use crate::placeholder::{Scanner, Token};
+use crate::{Error, IoOperation, Substitutor};
+use std::path::{Path, PathBuf};
+
+/// A placeholder occurrence within a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Occurrence {
+    /// Path of the file containing the placeholder.
+    pub path: PathBuf,
+    /// Line of the placeholder start, starting at 1.
+    pub line: usize,
+    /// Column (in characters) of the placeholder start, starting at 1.
+    pub column: usize,
+    /// Variable name referenced by the placeholder.
+    pub name: String,
+}
+
+/// Search for placeholders in a single file.
+pub fn search_file<P>(path: P, substitutor: &Substitutor) -> Result<Vec<Occurrence>, Error>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    Ok(search_content(path, &content, substitutor))
+}
+
+/// Search for placeholders in all files under `root`, recursively.
+///
+/// Files are visited in sorted path order, and occurrences are reported in
+/// order within each file. Files which are not valid UTF-8 (e.g. binaries)
+/// are skipped, and symbolic links to directories are not followed.
+pub fn search_tree<P>(root: P, substitutor: &Substitutor) -> Result<Vec<Occurrence>, Error>
+where
+    P: AsRef<Path>,
+{
+    let mut found = Vec::new();
+    visit(root.as_ref(), substitutor, &mut found)?;
+    Ok(found)
+}
+
+fn visit(path: &Path, substitutor: &Substitutor, found: &mut Vec<Occurrence>) -> Result<(), Error> {
+    let metadata = std::fs::symlink_metadata(path).map_err(|e| io_error(path, e))?;
+    if metadata.is_dir() {
+        let mut entries = std::fs::read_dir(path)
+            .and_then(|entries| {
+                entries
+                    .map(|e| e.map(|e| e.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| io_error(path, e))?;
+        entries.sort();
+        for entry in entries {
+            visit(&entry, substitutor, found)?;
+        }
+        return Ok(());
+    }
+    if metadata.file_type().is_symlink() && std::fs::metadata(path).map_or(true, |m| m.is_dir()) {
+        return Ok(());
+    }
+
+    let content = std::fs::read(path).map_err(|e| io_error(path, e))?;
+    if let Ok(content) = std::str::from_utf8(&content) {
+        found.extend(search_content(path, content, substitutor));
+    }
+    Ok(())
+}
+
+/// Search for placeholders in `content`, read from `path`.
+fn search_content(path: &Path, content: &str, substitutor: &Substitutor) -> Vec<Occurrence> {
+    let mut found = Vec::new();
+    // Current line number, and byte offset of its start.
+    let mut line = 1;
+    let mut line_start = 0;
+    for token in Scanner::new(content, substitutor.syntax, substitutor.escape) {
+        let placeholder = match token {
+            Token::Placeholder(p) => p,
+            Token::Escape { .. } => continue,
+        };
+        let start = placeholder.span().start;
+        let before = &content[line_start..start];
+        if let Some(last) = before.rfind('\n') {
+            line += before.matches('\n').count();
+            line_start += last + 1;
+        }
+        found.push(Occurrence {
+            path: path.to_path_buf(),
+            line,
+            column: content[line_start..start].chars().count() + 1,
+            name: placeholder.name().to_string(),
+        });
+    }
+    found
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Io {
+        operation: IoOperation::Read,
+        path: Some(path.to_path_buf()),
+        source: e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Syntax;
+
+    #[test]
+    fn content_positions() {
+        let path = Path::new("t");
+        let content = "${a}\n  é ${b} $${c}\n\n${d\n} ${e:-\n}";
+        let found: Vec<_> = search_content(path, content, &Substitutor::new())
+            .into_iter()
+            .map(|o| (o.line, o.column, o.name))
+            .collect();
+        let expected = vec![
+            (1, 1, "a".to_string()),
+            (2, 5, "b".to_string()),
+            (4, 1, "d\n".to_string()),
+            (5, 3, "e".to_string()),
+        ];
+        assert_eq!(found, expected);
+
+        let engine = Substitutor::new().syntax(Syntax::DoubleBraces);
+        let found = search_content(path, "${a} {{ b }}", &engine);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].column, found[0].name.as_str()), (6, "b"));
+    }
+
+    #[test]
+    fn tree_search() {
+        let root = std::env::temp_dir().join(format!("envsubst-search-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("b.conf"), "host=${HOST}\nport=${PORT}").unwrap();
+        std::fs::write(root.join("sub/a.conf"), "url=${URL}").unwrap();
+        std::fs::write(root.join("binary"), b"\xff${BIN}").unwrap();
+        std::fs::write(root.join("a.txt"), "nothing here").unwrap();
+
+        let found = search_tree(&root, &Substitutor::new());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let found: Vec<_> = found
+            .unwrap()
+            .into_iter()
+            .map(|o| {
+                (
+                    o.path.strip_prefix(&root).unwrap().to_path_buf(),
+                    o.line,
+                    o.name,
+                )
+            })
+            .collect();
+        let expected = vec![
+            (PathBuf::from("b.conf"), 1, "HOST".to_string()),
+            (PathBuf::from("b.conf"), 2, "PORT".to_string()),
+            (PathBuf::from("sub/a.conf"), 1, "URL".to_string()),
+        ];
+        assert_eq!(found, expected);
+
+        search_tree(root.join("missing"), &Substitutor::new()).unwrap_err();
+    }
+}
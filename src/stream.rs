@@ -0,0 +1,339 @@
+//! Streaming substitution.
+
+use crate::placeholder::{Escape, Scanner, Token};
+use crate::{Error, IoOperation, Position, Substitutor, Syntax};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Substitute variables while copying `reader` to `writer`.
+///
+/// Input is buffered until it can be rendered on its own, i.e. up to the
+/// last position where a placeholder or escape sequence could still be
+/// completed by further input. At most [`MAX_PENDING_LEN`] bytes are held
+/// back this way: past that, the opening character is taken verbatim, as
+/// for a placeholder which is never terminated.
+pub(crate) fn render_stream<R, W>(
+    substitutor: &Substitutor,
+    mut reader: R,
+    mut writer: W,
+    variables: &HashMap<String, String>,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+{
+    substitutor.check_variables(variables)?;
+
+    let mut engine = substitutor.clone();
+    let mut pending = Vec::new();
+    // Placeholders rendered so far, as the limit applies to the whole input.
+    let mut total = 0;
+    // Position of `pending` within the whole input.
+    let mut position = Position::default();
+    loop {
+        let read = {
+            let chunk = reader
+                .fill_buf()
+                .map_err(|e| io_error(IoOperation::Read, e))?;
+            pending.extend_from_slice(chunk);
+            chunk.len()
+        };
+        reader.consume(read);
+        let eof = read == 0;
+
+        let text = match std::str::from_utf8(&pending) {
+            Ok(text) => text,
+            // A multi-byte character may be split across reads.
+            Err(e) if !eof && e.error_len().is_none() => {
+                std::str::from_utf8(&pending[..e.valid_up_to()]).unwrap_or_default()
+            }
+            Err(e) => {
+                let offset = position.offset + e.valid_up_to();
+                return Err(Error::InvalidUtf8 { offset });
+            }
+        };
+        let (text, verbatim) = if eof {
+            (text, "")
+        } else {
+            let len = settled_len(text, engine.syntax, engine.escape);
+            match text.len() - len {
+                // Held back characters are ASCII openers, on char boundaries.
+                held if held > MAX_PENDING_LEN => (&text[..len], &text[len..len + 1]),
+                _ => (&text[..len], ""),
+            }
+        };
+
+        // Report unresolved placeholders relative to the whole input.
+        if let Some(callback) = substitutor.on_unresolved.clone() {
+            let base = position.offset;
+            engine.on_unresolved = Some(Arc::new(move |name: &str, span: Range<usize>| {
+                callback(name, span.start + base..span.end + base)
+            }));
+        }
+
+        engine.max_placeholders = substitutor.max_placeholders.map(|limit| limit - total);
+        let output = engine
+            .render_checked(text, variables, |_, _| total += 1)
+            .map_err(|e| match e {
+                Error::TooManyPlaceholders { position, .. } => Error::TooManyPlaceholders {
+                    limit: substitutor.max_placeholders.unwrap_or_default(),
+                    position,
+                },
+                e => e,
+            })
+            .map_err(|e| e.shifted(&position))?;
+        writer
+            .write_all(output.as_bytes())
+            .map_err(|e| io_error(IoOperation::Write, e))?;
+        writer
+            .write_all(verbatim.as_bytes())
+            .map_err(|e| io_error(IoOperation::Write, e))?;
+
+        if eof {
+            break;
+        }
+        position = position.advance(text).advance(verbatim);
+        let len = text.len() + verbatim.len();
+        pending.drain(..len);
+    }
+    writer.flush().map_err(|e| io_error(IoOperation::Write, e))
+}
+
+/// Maximum length of input held back for an unterminated placeholder, in
+/// bytes.
+pub(crate) const MAX_PENDING_LEN: usize = 4096;
+
+/// Length of the prefix of `input` which can be rendered without more input.
+///
+/// The prefix stops before the last opening `$` or `{` if its placeholder
+/// is not terminated yet, and never ends with characters which could start
+/// an escape sequence or placeholder. Escape sequences never need a closing
+/// brace, so they are settled as soon as they are recognized.
+fn settled_len(input: &str, syntax: Syntax, escape: Escape) -> usize {
+    let bytes = input.as_bytes();
+    let is_opener = |b: &u8| match syntax {
+        Syntax::DoubleBraces => *b == b'{',
+        _ => *b == b'$' || *b == b'{',
+    };
+
+    let mut len = bytes.len();
+    if let Some(last) = bytes.iter().rposition(is_opener) {
+        let rest = &bytes[last + 1..];
+        let settled = match (bytes[last], syntax) {
+            (b'{', Syntax::DoubleBraces) => rest
+                .iter()
+                .position(|b| *b == b'}')
+                .map_or(false, |end| end + 1 < rest.len()),
+            (b'{', _) if last > 0 && bytes[last - 1] == b'$' => rest.contains(&b'}'),
+            (b'$', Syntax::Dollar) => rest
+                .iter()
+                .any(|b| !(b.is_ascii_alphanumeric() || *b == b'_')),
+            _ => !rest.is_empty(),
+        };
+        if !settled {
+            len = last;
+        }
+    }
+    while len > 0 && matches!(bytes[len - 1], b'$' | b'{' | b'\\') {
+        len -= 1;
+    }
+
+    // An escape sequence may start the held back characters. It is only
+    // recognized before an opener, so `$$` and `\$` are settled along with
+    // the literal `{` or name character after them.
+    match Scanner::new(&input[len..], syntax, escape).next() {
+        Some(Token::Escape { span, .. }) => {
+            let end = match syntax {
+                Syntax::DoubleBraces => len + span.end,
+                _ => len + span.end + 1,
+            };
+            end + settled_len(&input[end..], syntax, escape)
+        }
+        _ => len,
+    }
+}
+
+fn io_error(operation: IoOperation, e: std::io::Error) -> Error {
+    Error::Io {
+        operation,
+        path: None,
+        source: e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Missing;
+    use std::io::BufReader;
+
+    #[test]
+    fn split_reads() {
+        let mut env = HashMap::new();
+        env.insert("a".to_string(), "x".to_string());
+        env.insert("long_name".to_string(), "y".to_string());
+
+        let templates = [
+            "plain text, é and ü",
+            "${a}${long_name} ${a:-default}${b:-d\nef} ${é} {{ a }}",
+            r"$${a} $$ \${a} \{{a}} $$$${a} $a $long_name. ${a $",
+            "{{a}} {{ long_name }}} {{{a}} {{b:-{}} {a} ${",
+            "${a${long_name}}${",
+        ];
+        let engines = [
+            Substitutor::new(),
+            Substitutor::new().syntax(Syntax::Dollar),
+            Substitutor::new().syntax(Syntax::DoubleBraces),
+            Substitutor::new().escape(Escape::Backslash),
+            Substitutor::new()
+                .syntax(Syntax::DoubleBraces)
+                .escape(Escape::Backslash),
+            Substitutor::new().missing(Missing::Empty),
+        ];
+        for engine in &engines {
+            for template in &templates {
+                let expected = engine.render(template, &env).unwrap();
+                for capacity in 1..8 {
+                    let reader = BufReader::with_capacity(capacity, template.as_bytes());
+                    let mut output = Vec::new();
+                    engine.render_stream(reader, &mut output, &env).unwrap();
+                    assert_eq!(
+                        String::from_utf8(output).unwrap(),
+                        expected,
+                        "{:?} with {:?}, reading by {}",
+                        template,
+                        engine,
+                        capacity
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stream_unterminated() {
+        let mut env = HashMap::new();
+        env.insert("a".to_string(), "x".to_string());
+
+        let long = "é".repeat(64 * 1024);
+        let templates = [
+            format!("${{a}} ${{{} ${{a}} $${{{} ${{a}}", long, long),
+            format!("$${{{} $$$${{{} \\${{{} ${{a}}", long, long, long),
+            format!("{{{{{} \\{{{{{} \\{{{{{{{} {{{{a}}}}", long, long, long),
+        ];
+        let engines = [
+            Substitutor::new(),
+            Substitutor::new().escape(Escape::Backslash),
+            Substitutor::new()
+                .syntax(Syntax::DoubleBraces)
+                .escape(Escape::Backslash),
+        ];
+        for engine in &engines {
+            for template in &templates {
+                let expected = engine.render(template, &env).unwrap();
+                for capacity in &[1, 7, 64] {
+                    let reader = BufReader::with_capacity(*capacity, template.as_bytes());
+                    let mut output = Vec::new();
+                    engine.render_stream(reader, &mut output, &env).unwrap();
+                    assert!(output == expected.as_bytes(), "reading by {}", capacity);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stream_unresolved() {
+        let unresolved = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = unresolved.clone();
+        let engine = Substitutor::new().on_unresolved(move |name, span| {
+            log.lock().unwrap().push((name.to_string(), span));
+        });
+
+        let template = "${a} ${b:-x} ${c}\n${d}";
+        let reader = BufReader::with_capacity(2, template.as_bytes());
+        engine
+            .render_stream(reader, Vec::new(), &HashMap::new())
+            .unwrap();
+        let spans: Vec<_> = unresolved
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, s)| &template[s.clone()])
+            .collect();
+        assert_eq!(spans, vec!["${a}", "${c}", "${d}"]);
+    }
+
+    #[test]
+    fn stream_errors() {
+        let env = HashMap::new();
+        let engine = Substitutor::new().max_placeholders(2);
+        let reader = BufReader::with_capacity(1, "${a}${b}${c}".as_bytes());
+        match engine.render_stream(reader, Vec::new(), &env).unwrap_err() {
+            Error::TooManyPlaceholders { limit, position } => {
+                assert_eq!((limit, position.offset), (2, 8));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+        let reader = BufReader::with_capacity(1, "${a}${b}".as_bytes());
+        engine.render_stream(reader, Vec::new(), &env).unwrap();
+
+        let input: &[u8] = b"valid \xff invalid";
+        let err = Substitutor::new()
+            .render_stream(input, Vec::new(), &env)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "envsubst error: input is not valid UTF-8");
+        let reader = BufReader::with_capacity(1, input);
+        let err = Substitutor::new()
+            .render_stream(reader, Vec::new(), &env)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidUtf8 { offset: 6 }));
+
+        struct Failing;
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "broken"))
+            }
+        }
+        impl Write for Failing {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "broken"))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let engine = Substitutor::new();
+        let err = engine
+            .render_stream(BufReader::new(Failing), Vec::new(), &env)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: failed to read input: broken"
+        );
+        let err = engine
+            .render_stream("text".as_bytes(), Failing, &env)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: failed to write output: broken"
+        );
+
+        let engine = Substitutor::new().missing(Missing::Error);
+        let reader = BufReader::with_capacity(
+            3,
+            "a
+b ${c}
+ ${d}"
+                .as_bytes(),
+        );
+        match engine.render_stream(reader, Vec::new(), &env).unwrap_err() {
+            Error::MissingVariable { name, position, .. } => {
+                assert_eq!(name, "c");
+                assert_eq!((position.offset, position.line, position.column), (4, 2, 3));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+}
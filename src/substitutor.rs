@@ -0,0 +1,1180 @@
+//! Configurable substitution engine.
+
+use crate::error::{snippet_at, truncate_name};
+use crate::filter::{self, Filter, Filters};
+use crate::placeholder::{Escape, Placeholder, Scanner, Syntax, Token};
+use crate::plan::RenderPlan;
+use crate::resolver::VariableResolver;
+use crate::{closest_match, stream, validate_key, validate_value, Error, Position, Template};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Behavior for placeholders referencing undefined variables.
+///
+/// This only applies to placeholders without a default value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Missing {
+    /// Leave the placeholder untouched in the output.
+    Keep,
+    /// Replace the placeholder with an empty string.
+    Empty,
+    /// Fail the substitution.
+    Error,
+}
+
+/// Case conversion rules, for the `lower` and `upper` filters.
+///
+/// Both are locale-independent, so that templates render the same on any
+/// host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CaseFolding {
+    /// Full Unicode case mapping, e.g. `ß` uppercases to `SS`.
+    Unicode,
+    /// ASCII letters only, leaving other characters untouched (faster).
+    Ascii,
+}
+
+impl CaseFolding {
+    pub(crate) fn lower(self, value: &str) -> String {
+        match self {
+            CaseFolding::Unicode => value.to_lowercase(),
+            CaseFolding::Ascii => value.to_ascii_lowercase(),
+        }
+    }
+
+    pub(crate) fn upper(self, value: &str) -> String {
+        match self {
+            CaseFolding::Unicode => value.to_uppercase(),
+            CaseFolding::Ascii => value.to_ascii_uppercase(),
+        }
+    }
+}
+
+/// Variables storage, abstracting over the supported maps and resolvers.
+pub(crate) trait Lookup {
+    /// Value of variable `name`, if set.
+    fn get(&self, name: &str) -> Option<Cow<'_, str>>;
+
+    /// All known variables, with their value if set.
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, Option<&str>)> + '_>;
+}
+
+impl Lookup for HashMap<String, String> {
+    fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+        HashMap::get(self, name).map(|v| Cow::Borrowed(v.as_str()))
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, Option<&str>)> + '_> {
+        Box::new(self.iter().map(|(k, v)| (k.as_str(), Some(v.as_str()))))
+    }
+}
+
+impl Lookup for HashMap<String, Option<String>> {
+    fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+        HashMap::get(self, name)?.as_deref().map(Cow::Borrowed)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, Option<&str>)> + '_> {
+        Box::new(self.iter().map(|(k, v)| (k.as_str(), v.as_deref())))
+    }
+}
+
+/// Variables from a [`VariableResolver`], which cannot be listed.
+pub(crate) struct Resolved<'a, R: ?Sized>(pub(crate) &'a R);
+
+impl<R> Lookup for Resolved<'_, R>
+where
+    R: VariableResolver + ?Sized,
+{
+    fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.0.resolve(name)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, Option<&str>)> + '_> {
+        Box::new(std::iter::empty())
+    }
+}
+
+/// Configurable variables substitution engine.
+///
+/// This allows tuning the placeholder syntax and the substitution policies,
+/// and can then be used to render any number of templates. A default
+/// `Substitutor` behaves like [`substitute`](crate::substitute).
+///
+/// ```rust
+/// use envsubst::{Missing, Substitutor, Syntax};
+///
+/// let engine = Substitutor::new()
+///     .syntax(Syntax::DoubleBraces)
+///     .missing(Missing::Error);
+///
+/// let mut context = std::collections::HashMap::new();
+/// context.insert("name".to_string(), "world".to_string());
+/// assert_eq!(engine.render("Hello {{ name }}!", &context).unwrap(), "Hello world!");
+/// assert!(engine.render("Hello {{ other }}!", &context).is_err());
+/// ```
+#[derive(Clone)]
+pub struct Substitutor {
+    pub(crate) syntax: Syntax,
+    pub(crate) missing: Missing,
+    pub(crate) escape: Escape,
+    pub(crate) validate_values: bool,
+    pub(crate) max_placeholders: Option<usize>,
+    pub(crate) max_name_len: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) on_unresolved: Option<Arc<UnresolvedFn>>,
+    pub(crate) filters: Option<Filters>,
+    pub(crate) case_folding: CaseFolding,
+    pub(crate) defaults: Option<Arc<HashMap<String, String>>>,
+}
+
+/// Callback for unresolved placeholders, see [`Substitutor::on_unresolved`].
+type UnresolvedFn = dyn Fn(&str, Range<usize>) + Send + Sync;
+
+impl fmt::Debug for Substitutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Substitutor")
+            .field("syntax", &self.syntax)
+            .field("missing", &self.missing)
+            .field("escape", &self.escape)
+            .field("validate_values", &self.validate_values)
+            .field("max_placeholders", &self.max_placeholders)
+            .field("max_name_len", &self.max_name_len)
+            .field("max_depth", &self.max_depth)
+            .field("on_unresolved", &self.on_unresolved.is_some())
+            .field(
+                "defaults",
+                &self
+                    .defaults
+                    .as_ref()
+                    .map(|defaults| defaults.keys().collect::<std::collections::BTreeSet<_>>()),
+            )
+            .field(
+                "filters",
+                &self
+                    .filters
+                    .as_ref()
+                    .map(|filters| filters.keys().collect::<Vec<_>>()),
+            )
+            .field("case_folding", &self.case_folding)
+            .finish()
+    }
+}
+
+impl Default for Substitutor {
+    fn default() -> Self {
+        Self {
+            syntax: Syntax::Braced,
+            missing: Missing::Keep,
+            escape: Escape::DoubleDollar,
+            validate_values: false,
+            max_placeholders: None,
+            max_name_len: None,
+            max_depth: None,
+            on_unresolved: None,
+            filters: None,
+            case_folding: CaseFolding::Unicode,
+            defaults: None,
+        }
+    }
+}
+
+impl Substitutor {
+    /// Create a substitutor with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the placeholder syntax (default: `Syntax::Braced`).
+    pub fn syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Set the behavior for undefined variables (default: `Missing::Keep`).
+    pub fn missing(mut self, missing: Missing) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    /// Set the escaping rules (default: `Escape::DoubleDollar`).
+    pub fn escape(mut self, escape: Escape) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Whether to reject values containing `$`, `{` or `}` (default: `false`).
+    pub fn validate_values(mut self, validate_values: bool) -> Self {
+        self.validate_values = validate_values;
+        self
+    }
+
+    /// Limit the number of placeholders processed per render (default: none).
+    ///
+    /// Rendering a template with more placeholders fails, which bounds the
+    /// work done for untrusted templates.
+    pub fn max_placeholders(mut self, limit: usize) -> Self {
+        self.max_placeholders = Some(limit);
+        self
+    }
+
+    /// Limit the length of placeholder names, in bytes (default: none).
+    ///
+    /// Compiling or rendering a template with a longer name fails before
+    /// looking it up, with only the start of the name in the error, which
+    /// keeps errors readable for corrupted or hostile templates.
+    ///
+    /// ```rust
+    /// let engine = envsubst::Substitutor::new().max_name_len(64);
+    /// let template = format!("${{{}}}", "A".repeat(1000));
+    /// let err = engine.render(&template, &Default::default()).unwrap_err();
+    /// assert!(err.to_string().len() < 200);
+    /// ```
+    pub fn max_name_len(mut self, limit: usize) -> Self {
+        self.max_name_len = Some(limit);
+        self
+    }
+
+    /// Expand placeholders in variable values too, up to `max_depth` nested
+    /// levels (default: disabled).
+    ///
+    /// Values are expanded with the same settings as templates, e.g. with
+    /// `BASE = "${SCHEME}://${HOST}"` then `${BASE}` renders as a full URL.
+    /// Values which reference each other in a cycle are an error, as well as
+    /// values which still have placeholders past `max_depth` levels. Default
+    /// values of placeholders are not expanded.
+    ///
+    /// ```rust
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("BASE".to_string(), "${SCHEME}://${HOST}".to_string());
+    /// context.insert("SCHEME".to_string(), "https".to_string());
+    /// context.insert("HOST".to_string(), "example.com".to_string());
+    ///
+    /// let engine = envsubst::Substitutor::new().recursive(4);
+    /// let out = engine.render("${BASE}/login", &context).unwrap();
+    /// assert_eq!(out, "https://example.com/login");
+    /// ```
+    pub fn recursive(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Take values from `defaults` for variables missing from the rendered
+    /// variables (default: none).
+    ///
+    /// This keeps default values separate from the ones provided at each
+    /// render, e.g. product defaults from user overrides. Defaults take
+    /// precedence over the default values of placeholders, and are validated
+    /// like other variables.
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    ///
+    /// let mut defaults = HashMap::new();
+    /// defaults.insert("port".to_string(), "8080".to_string());
+    /// defaults.insert("host".to_string(), "localhost".to_string());
+    /// let engine = envsubst::Substitutor::new().defaults(defaults);
+    ///
+    /// let mut context = HashMap::new();
+    /// context.insert("host".to_string(), "example.com".to_string());
+    /// let out = engine.render("${host}:${port:-80}", &context).unwrap();
+    /// assert_eq!(out, "example.com:8080");
+    /// ```
+    pub fn defaults(mut self, defaults: HashMap<String, String>) -> Self {
+        self.defaults = Some(Arc::new(defaults));
+        self
+    }
+
+    /// Call `callback` for each placeholder left unresolved (default: none).
+    ///
+    /// This applies to placeholders without a value nor a default, when
+    /// undefined variables are not an error. The callback gets the variable
+    /// name and the byte range of the placeholder in the template.
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let unresolved = Arc::new(Mutex::new(Vec::new()));
+    /// let log = unresolved.clone();
+    /// let engine = envsubst::Substitutor::new().on_unresolved(move |name, span| {
+    ///     log.lock().unwrap().push((name.to_string(), span));
+    /// });
+    ///
+    /// let context = std::collections::HashMap::new();
+    /// assert_eq!(engine.render("x=${X}", &context).unwrap(), "x=${X}");
+    /// assert_eq!(*unresolved.lock().unwrap(), vec![("X".to_string(), 2..6)]);
+    /// ```
+    pub fn on_unresolved<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str, Range<usize>) + Send + Sync + 'static,
+    {
+        self.on_unresolved = Some(Arc::new(callback));
+        self
+    }
+
+    /// Whether to apply filters in placeholders (default: `false`).
+    ///
+    /// Filters transform values, with placeholders of the form
+    /// `${foo|filter}` or `${foo|filter|other}`. Built-in filters are
+    /// `lower`, `upper`, `trim` and `base64`, and more can be added with
+    /// [`register_filter`](Self::register_filter). Filters apply to default
+    /// values too, as in `${foo|upper:-default}`, and unknown filters are an
+    /// error.
+    ///
+    /// ```rust
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("HOST".to_string(), " Example.COM ".to_string());
+    ///
+    /// let engine = envsubst::Substitutor::new().filters(true);
+    /// let out = engine.render("https://${HOST|trim|lower}/", &context).unwrap();
+    /// assert_eq!(out, "https://example.com/");
+    /// ```
+    pub fn filters(mut self, enabled: bool) -> Self {
+        self.filters = match (enabled, self.filters.take()) {
+            (false, _) => None,
+            (true, filters) => Some(filters.unwrap_or_else(filter::builtins)),
+        };
+        self
+    }
+
+    /// Set the case conversion rules of the `lower` and `upper` filters
+    /// (default: `CaseFolding::Unicode`).
+    ///
+    /// ```rust
+    /// use envsubst::{CaseFolding, Substitutor};
+    ///
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("city".to_string(), "Zürich".to_string());
+    ///
+    /// let engine = Substitutor::new().filters(true);
+    /// assert_eq!(engine.render("${city|upper}", &context).unwrap(), "ZÜRICH");
+    /// let engine = engine.case_folding(CaseFolding::Ascii);
+    /// assert_eq!(engine.render("${city|upper}", &context).unwrap(), "ZüRICH");
+    /// ```
+    pub fn case_folding(mut self, case_folding: CaseFolding) -> Self {
+        self.case_folding = case_folding;
+        self
+    }
+
+    /// Add filter `name`, enabling [`filters`](Self::filters).
+    ///
+    /// This replaces any filter with the same name, including built-ins.
+    ///
+    /// ```rust
+    /// use envsubst::{Error, Substitutor};
+    ///
+    /// let engine = Substitutor::new().register_filter("port", |value| {
+    ///     match value.parse::<u16>() {
+    ///         Ok(port) => Ok(port.to_string()),
+    ///         Err(e) => Err(Error::Filter {
+    ///             filter: "port".to_string(),
+    ///             message: e.to_string(),
+    ///         }),
+    ///     }
+    /// });
+    ///
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("PORT".to_string(), "http".to_string());
+    /// assert!(engine.render("${PORT|port}", &context).is_err());
+    /// ```
+    pub fn register_filter<S, F>(mut self, name: S, filter: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&str) -> Result<String, Error> + Send + Sync + 'static,
+    {
+        self = self.filters(true);
+        if let Some(filters) = self.filters.as_mut() {
+            filters.insert(name.into(), Filter::Custom(Arc::new(filter)));
+        }
+        self
+    }
+
+    /// Substitute variables in a template string.
+    pub fn render<T>(
+        &self,
+        template: T,
+        variables: &HashMap<String, String>,
+    ) -> Result<String, Error>
+    where
+        T: AsRef<str>,
+    {
+        self.render_with(template.as_ref(), variables, |_, _| {})
+    }
+
+    /// Substitute variables which may be explicitly unset.
+    ///
+    /// A `None` value is handled exactly like a variable which is not in
+    /// `variables`: placeholders fall back to their default, or to the
+    /// missing-variable behavior. `Some("")` is instead a legitimate empty
+    /// value, which takes precedence over defaults.
+    ///
+    /// ```rust
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("unset".to_string(), None);
+    /// context.insert("empty".to_string(), Some(String::new()));
+    ///
+    /// let engine = envsubst::Substitutor::new();
+    /// let out = engine.render_optional("[${unset:-a}] [${empty:-b}]", &context).unwrap();
+    /// assert_eq!(out, "[a] []");
+    /// ```
+    pub fn render_optional<T>(
+        &self,
+        template: T,
+        variables: &HashMap<String, Option<String>>,
+    ) -> Result<String, Error>
+    where
+        T: AsRef<str>,
+    {
+        self.render_with(template.as_ref(), variables, |_, _| {})
+    }
+
+    /// Substitute variables resolved on demand by `resolver`.
+    ///
+    /// Only variables referenced by the template are resolved, each time
+    /// they are needed. As resolvers cannot be listed, names are not
+    /// validated upfront and errors for undefined variables have no hint,
+    /// but values are validated when resolved if enabled.
+    ///
+    /// ```rust
+    /// let resolver = |name: &str| match name {
+    ///     "user" => Some("admin".to_string()),
+    ///     _ => None,
+    /// };
+    ///
+    /// let engine = envsubst::Substitutor::new();
+    /// let out = engine.render_resolver("${user}@${host:-localhost}", &resolver).unwrap();
+    /// assert_eq!(out, "admin@localhost");
+    /// ```
+    pub fn render_resolver<T, R>(&self, template: T, resolver: &R) -> Result<String, Error>
+    where
+        T: AsRef<str>,
+        R: VariableResolver + ?Sized,
+    {
+        self.render_with(template.as_ref(), &Resolved(resolver), |_, _| {})
+    }
+
+    /// Substitute variables while copying `reader` to `writer`.
+    ///
+    /// The input is processed incrementally, so memory usage does not grow
+    /// with the input size but only with the length of placeholders, even
+    /// when they are split across reads. The output is the same as for
+    /// [`render`](Self::render) on the whole input, but may be partially
+    /// written when an error occurs. To keep memory bounded, placeholders
+    /// longer than 4 KiB, e.g. after a stray `${`, are taken verbatim.
+    ///
+    /// ```rust
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("name".to_string(), "world".to_string());
+    ///
+    /// let mut output = Vec::new();
+    /// let engine = envsubst::Substitutor::new();
+    /// engine.render_stream("Hello ${name}!".as_bytes(), &mut output, &context).unwrap();
+    /// assert_eq!(output, b"Hello world!");
+    /// ```
+    pub fn render_stream<R, W>(
+        &self,
+        reader: R,
+        writer: W,
+        variables: &HashMap<String, String>,
+    ) -> Result<(), Error>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        stream::render_stream(self, reader, writer, variables)
+    }
+
+    /// Validate variable names, and values if enabled.
+    pub(crate) fn check_variables<V>(&self, variables: &V) -> Result<(), Error>
+    where
+        V: Lookup + ?Sized,
+    {
+        let defaults = self.defaults.iter().flat_map(|d| Lookup::entries(&**d));
+        for (k, v) in variables.entries().chain(defaults) {
+            validate_key(k)?;
+            match v {
+                Some(v) if self.validate_values => validate_value(k, v)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of variable `name`, from `variables` or else from defaults.
+    pub(crate) fn lookup<'a, V>(&'a self, variables: &'a V, name: &str) -> Option<Cow<'a, str>>
+    where
+        V: Lookup + ?Sized,
+    {
+        variables
+            .get(name)
+            .or_else(|| Lookup::get(&**self.defaults.as_ref()?, name))
+    }
+
+    /// Parse a template once with these settings, for repeated rendering.
+    pub fn compile<T>(&self, template: T) -> Result<Template, Error>
+    where
+        T: AsRef<str>,
+    {
+        Template::compile(self, template.as_ref())
+    }
+
+    /// Plan the rendering of `template`, see [`Template::plan`].
+    pub fn plan<T>(
+        &self,
+        template: T,
+        variables: &HashMap<String, String>,
+    ) -> Result<RenderPlan, Error>
+    where
+        T: AsRef<str>,
+    {
+        self.compile(template)?.plan(variables)
+    }
+
+    /// Parse several templates at once, e.g. at startup.
+    ///
+    /// All templates are parsed even if some fail, so that every broken
+    /// template is reported at once: errors come with the index of their
+    /// template in `templates`.
+    ///
+    /// ```rust
+    /// let engine = envsubst::Substitutor::new().max_placeholders(1);
+    ///
+    /// let templates = engine.precompile(&["${greeting}", "plain"]).unwrap();
+    /// assert_eq!(templates.len(), 2);
+    ///
+    /// let errors = engine.precompile(&["${a}${b}", "ok", "${c}${d}"]).unwrap_err();
+    /// let failed: Vec<_> = errors.iter().map(|(index, _)| *index).collect();
+    /// assert_eq!(failed, vec![0, 2]);
+    /// ```
+    pub fn precompile<I, T>(&self, templates: I) -> Result<Vec<Template>, Vec<(usize, Error)>>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut compiled = Vec::new();
+        let mut errors = Vec::new();
+        for (index, template) in templates.into_iter().enumerate() {
+            match self.compile(template) {
+                Ok(template) => compiled.push(template),
+                Err(e) => errors.push((index, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(compiled)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Render `template` in a single pass, calling `visit` on each placeholder.
+    ///
+    /// The boolean passed to `visit` tells whether the placeholder was
+    /// substituted with a value from `variables`.
+    pub(crate) fn render_with<V, F>(
+        &self,
+        template: &str,
+        variables: &V,
+        visit: F,
+    ) -> Result<String, Error>
+    where
+        V: Lookup + ?Sized,
+        F: FnMut(&Placeholder, bool),
+    {
+        self.check_variables(variables)?;
+        self.render_checked(template, variables, visit)
+    }
+
+    /// Like `render_with`, for variables already checked by `check_variables`.
+    pub(crate) fn render_checked<V, F>(
+        &self,
+        template: &str,
+        variables: &V,
+        mut visit: F,
+    ) -> Result<String, Error>
+    where
+        V: Lookup + ?Sized,
+        F: FnMut(&Placeholder, bool),
+    {
+        self.expand(template, variables, &mut visit, &mut Vec::new(), None)
+    }
+
+    /// Expand `value` of variable `name`, in recursive mode.
+    ///
+    /// `stack` holds the names of the variables being expanded, and `origin`
+    /// is the span of the top-level placeholder in the template.
+    pub(crate) fn expand_value<V>(
+        &self,
+        name: &str,
+        value: &str,
+        variables: &V,
+        stack: &mut Vec<String>,
+        origin: Range<usize>,
+    ) -> Result<String, Error>
+    where
+        V: Lookup + ?Sized,
+    {
+        if let Some(start) = stack.iter().position(|n| n == name) {
+            let mut chain = stack[start..].to_vec();
+            chain.push(name.to_string());
+            return Err(Error::RecursionCycle { chain });
+        }
+        let max_depth = self.max_depth.unwrap_or(0);
+        if stack.len() >= max_depth
+            && Scanner::new(value, self.syntax, self.escape)
+                .any(|token| matches!(token, Token::Placeholder(_)))
+        {
+            return Err(Error::RecursionLimit {
+                name: name.to_string(),
+                max_depth,
+            });
+        }
+
+        stack.push(name.to_string());
+        let expanded = self.expand(value, variables, &mut |_, _| {}, stack, Some(origin));
+        stack.pop();
+        expanded
+    }
+
+    fn expand<V, F>(
+        &self,
+        template: &str,
+        variables: &V,
+        visit: &mut F,
+        stack: &mut Vec<String>,
+        origin: Option<Range<usize>>,
+    ) -> Result<String, Error>
+    where
+        V: Lookup + ?Sized,
+        F: FnMut(&Placeholder, bool),
+    {
+        let mut output = String::with_capacity(template.len());
+        let mut last = 0;
+        let mut count = 0;
+        for token in Scanner::new(template, self.syntax, self.escape) {
+            let span = token.span();
+            let resolved: Option<Cow<'_, str>>;
+            let expanded: String;
+            let filtered: String;
+            let value = match &token {
+                Token::Escape { literal, .. } => *literal,
+                Token::Placeholder(placeholder) => {
+                    count += 1;
+                    if let Some(limit) = self.max_placeholders.filter(|limit| count > *limit) {
+                        let position = Position::locate(template, span.start);
+                        return Err(Error::TooManyPlaceholders { limit, position });
+                    }
+                    let (name, filters) = self.split_filters(placeholder.name());
+                    self.check_name_len(name)
+                        .map_err(|e| e.at(template, span.start))?;
+                    resolved = self.lookup(variables, name);
+                    let found = resolved.as_deref();
+                    visit(placeholder, found.is_some());
+                    if let Some(value) = found.filter(|_| self.validate_values) {
+                        validate_value(name, value)?;
+                    }
+                    if let Some(message) = placeholder.required() {
+                        if found.map_or(true, str::is_empty) {
+                            return Err(required_error(name, message, template, span.start));
+                        }
+                    }
+                    let value = match (found, placeholder.default()) {
+                        (Some(value), _) if self.max_depth.is_some() => {
+                            // Errors in nested values are located at the top-level placeholder.
+                            let top = origin.is_none();
+                            let origin = origin.clone().unwrap_or_else(|| span.clone());
+                            expanded = self
+                                .expand_value(name, value, variables, stack, origin)
+                                .map_err(|e| if top { e.at(template, span.start) } else { e })?;
+                            &expanded
+                        }
+                        (Some(value), _) => value,
+                        (None, Some(default)) => default,
+                        (None, None) => {
+                            self.report_unresolved(name, origin.as_ref(), &span);
+                            match self.missing {
+                                Missing::Keep => continue,
+                                Missing::Empty => "",
+                                Missing::Error => {
+                                    return Err(missing_error(
+                                        name, template, span.start, variables,
+                                    ));
+                                }
+                            }
+                        }
+                    };
+                    match self.apply_filters(&filters, value) {
+                        Ok(Some(value)) => {
+                            filtered = value;
+                            &filtered
+                        }
+                        Ok(None) => value,
+                        Err(e) => return Err(e.at(template, span.start)),
+                    }
+                }
+            };
+            output.push_str(&template[last..span.start]);
+            output.push_str(value);
+            last = span.end;
+        }
+        output.push_str(&template[last..]);
+
+        Ok(output)
+    }
+
+    /// Check that variable `name` is within the length limit, if any.
+    pub(crate) fn check_name_len(&self, name: &str) -> Result<(), Error> {
+        match self.max_name_len {
+            Some(limit) if name.len() > limit => Err(Error::NameTooLong {
+                name: truncate_name(name),
+                len: name.len(),
+                limit,
+                position: Position::default(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Split a placeholder name into the variable name and its filters, if
+    /// filters are enabled.
+    pub(crate) fn split_filters<'a>(&self, name: &'a str) -> (&'a str, Vec<&'a str>) {
+        match self.filters {
+            Some(_) => filter::split_filters(name),
+            None => (name, Vec::new()),
+        }
+    }
+
+    /// Apply `names` filters to `value`, if any.
+    pub(crate) fn apply_filters(
+        &self,
+        names: &[&str],
+        value: &str,
+    ) -> Result<Option<String>, Error> {
+        match &self.filters {
+            Some(filters) if !names.is_empty() => {
+                filter::apply(filters, names, value, self.case_folding).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Report an unresolved placeholder at `span`, or at `origin` if set.
+    pub(crate) fn report_unresolved(
+        &self,
+        name: &str,
+        origin: Option<&Range<usize>>,
+        span: &Range<usize>,
+    ) {
+        if let Some(on_unresolved) = self.on_unresolved.as_ref() {
+            if self.missing != Missing::Error {
+                on_unresolved(name, origin.unwrap_or(span).clone());
+            }
+        }
+    }
+}
+
+/// Build the error for an undefined variable, with a hint if possible.
+///
+/// The placeholder is located at byte `offset` of `template`.
+pub(crate) fn missing_error<V>(name: &str, template: &str, offset: usize, variables: &V) -> Error
+where
+    V: Lookup + ?Sized,
+{
+    let defined = variables.entries().filter(|(_, v)| v.is_some());
+    Error::MissingVariable {
+        name: name.to_string(),
+        hint: closest_match(name, defined.map(|(k, _)| k)).map(str::to_string),
+        position: Position::locate(template, offset),
+        snippet: snippet_at(template, offset),
+    }
+}
+
+/// Build the error for a required variable which is undefined or empty.
+///
+/// The placeholder is located at byte `offset` of `template`.
+pub(crate) fn required_error(name: &str, message: &str, template: &str, offset: usize) -> Error {
+    Error::RequiredVariable {
+        name: name.to_string(),
+        message: message.to_string(),
+        position: Position::locate(template, offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> HashMap<String, String> {
+        let mut env = HashMap::new();
+        env.insert("HOST".to_string(), "example.com".to_string());
+        env.insert("PORT".to_string(), "8080".to_string());
+        env
+    }
+
+    #[test]
+    fn syntaxes() {
+        let env = context();
+
+        let engine = Substitutor::new().syntax(Syntax::Dollar);
+        let out = engine.render("$HOST:${PORT}/$HOSTNAME", &env).unwrap();
+        assert_eq!(out, "example.com:8080/$HOSTNAME");
+
+        let engine = Substitutor::new().syntax(Syntax::DoubleBraces);
+        let out = engine.render("{{HOST}}:{{ PORT }} ${HOST}", &env).unwrap();
+        assert_eq!(out, "example.com:8080 ${HOST}");
+    }
+
+    #[test]
+    fn missing_policies() {
+        let env = context();
+        let template = "${HOST}:${PROT}";
+
+        let out = Substitutor::new().render(template, &env).unwrap();
+        assert_eq!(out, "example.com:${PROT}");
+
+        let engine = Substitutor::new().missing(Missing::Empty);
+        assert_eq!(engine.render(template, &env).unwrap(), "example.com:");
+
+        let engine = Substitutor::new().missing(Missing::Error);
+        let err = engine.render(template, &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: variable 'PROT' is not defined (line 1: `${HOST}:${PROT}`), did you mean 'PORT'?"
+        );
+        assert_eq!(engine.render("${PROT:-80}", &env).unwrap(), "80");
+
+        match engine.render("ok\n  ${HOST} ${X}", &env).unwrap_err() {
+            Error::MissingVariable {
+                name,
+                hint,
+                position,
+                snippet,
+            } => {
+                assert_eq!((name.as_str(), hint), ("X", None));
+                assert_eq!(
+                    (position.offset, position.line, position.column),
+                    (13, 2, 11)
+                );
+                assert_eq!(snippet, "${HOST} ${X}");
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn required_variables() {
+        let mut env = context();
+        env.insert("EMPTY".to_string(), String::new());
+
+        let engine = Substitutor::new().missing(Missing::Empty);
+        let out = engine.render("${HOST:?unset}:${PORT:?}", &env).unwrap();
+        assert_eq!(out, "example.com:8080");
+
+        let err = engine
+            .render("${PROT:?port is required}", &env)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: variable 'PROT' is required: port is required"
+        );
+        match engine.render("ok\n ${EMPTY:?}", &env).unwrap_err() {
+            Error::RequiredVariable {
+                name,
+                message,
+                position,
+            } => {
+                assert_eq!((name.as_str(), message.as_str()), ("EMPTY", ""));
+                assert_eq!((position.line, position.column), (2, 2));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+
+        env.insert("URL".to_string(), "http://${HOST}${EMPTY:?}".to_string());
+        let err = engine.recursive(4).render("=${URL}", &env).unwrap_err();
+        assert!(matches!(err, Error::RequiredVariable { position, .. } if position.offset == 1));
+    }
+
+    #[test]
+    fn value_filters() {
+        let mut env = context();
+        env.insert("NAME".to_string(), " Ada ".to_string());
+
+        let template = "${NAME|trim|upper}@${HOST | base64} ${X|upper:-none}";
+        let out = Substitutor::new().render(template, &env).unwrap();
+        assert_eq!(out, "${NAME|trim|upper}@${HOST | base64} none");
+        let engine = Substitutor::new().filters(true);
+        let out = engine.render(template, &env).unwrap();
+        assert_eq!(out, "ADA@ZXhhbXBsZS5jb20= NONE");
+        assert_eq!(engine.render("${X|upper}", &env).unwrap(), "${X|upper}");
+
+        match engine.render("${HOST} ${HOST|rot13}", &env).unwrap_err() {
+            Error::UnknownFilter { filter, position } => {
+                assert_eq!((filter.as_str(), position.offset), ("rot13", 8));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+
+        let engine = Substitutor::new()
+            .register_filter("upper", |value| Ok(format!("<{}>", value)))
+            .register_filter("fail", |_| {
+                Err(Error::Filter {
+                    filter: "fail".to_string(),
+                    message: "always".to_string(),
+                })
+            });
+        assert_eq!(
+            engine.render("${HOST|upper}", &env).unwrap(),
+            "<example.com>"
+        );
+        assert_eq!(engine.render("${HOST|lower}", &env).unwrap(), "example.com");
+        let err = engine.render("${HOST|fail}", &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: filter 'fail' failed: always"
+        );
+        assert!(engine.filters(false).render("${HOST|fail}", &env).is_ok());
+    }
+
+    #[test]
+    fn resolved_values() {
+        let queried = std::cell::RefCell::new(Vec::new());
+        let resolver = |name: &str| {
+            queried.borrow_mut().push(name.to_string());
+            match name {
+                "HOST" => Some("example.com".to_string()),
+                "BAD" => Some("${x}".to_string()),
+                _ => None,
+            }
+        };
+
+        let engine = Substitutor::new();
+        let out = engine.render_resolver("${HOST}:${PORT:-80} ${HOST}", &resolver);
+        assert_eq!(out.unwrap(), "example.com:80 example.com");
+        assert_eq!(*queried.borrow(), vec!["HOST", "PORT", "HOST"]);
+        assert_eq!(
+            engine.render_resolver("${HOST}", &context()).unwrap(),
+            engine.render("${HOST}", &context()).unwrap()
+        );
+
+        let engine = Substitutor::new().missing(Missing::Error);
+        let err = engine.render_resolver("${HOTS}", &resolver).unwrap_err();
+        assert!(matches!(err, Error::MissingVariable { hint: None, .. }));
+        assert_eq!(engine.render_resolver("${BAD}", &resolver).unwrap(), "${x}");
+        let err = engine
+            .validate_values(true)
+            .render_resolver("${BAD}", &resolver)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidValue { .. }));
+    }
+
+    #[test]
+    fn defaults_map() {
+        let mut defaults = HashMap::new();
+        defaults.insert("PORT".to_string(), "80".to_string());
+        defaults.insert("SCHEME".to_string(), "http".to_string());
+        defaults.insert("URL".to_string(), "${SCHEME}://${HOST}:${PORT}".to_string());
+        let engine = Substitutor::new().defaults(defaults.clone());
+
+        let out = engine.render("${SCHEME:-https}://${HOST}:${PORT}", &context());
+        assert_eq!(out.unwrap(), "http://example.com:8080");
+        let out = engine.render("${SCHEME}://${HOST}", &HashMap::new());
+        assert_eq!(out.unwrap(), "http://${HOST}");
+        let out = engine.clone().recursive(2).render("${URL}", &context());
+        assert_eq!(out.unwrap(), "http://example.com:8080");
+
+        let mut optional = HashMap::new();
+        optional.insert("SCHEME".to_string(), None);
+        let out = engine.render_optional("${SCHEME}", &optional).unwrap();
+        assert_eq!(out, "http");
+
+        defaults.insert("${BAD}".to_string(), String::new());
+        let engine = Substitutor::new().defaults(defaults);
+        let err = engine.render("${PORT}", &context()).unwrap_err();
+        assert!(matches!(err, Error::InvalidKey { .. }));
+    }
+
+    #[test]
+    fn escapes() {
+        let env = context();
+
+        let engine = Substitutor::new().escape(Escape::DoubleDollar);
+        let out = engine
+            .render("$${HOST} is ${HOST}, $$ stays", &env)
+            .unwrap();
+        assert_eq!(out, "${HOST} is example.com, $$ stays");
+
+        let engine = Substitutor::new().escape(Escape::None);
+        let out = engine.render("$${HOST}", &env).unwrap();
+        assert_eq!(out, "$example.com");
+
+        let engine = Substitutor::new().escape(Escape::Backslash);
+        let out = engine.render(r"\${HOST} is ${HOST}", &env).unwrap();
+        assert_eq!(out, "${HOST} is example.com");
+    }
+
+    #[test]
+    fn placeholders_limit() {
+        let env = context();
+        let engine = Substitutor::new().max_placeholders(2);
+
+        assert_eq!(
+            engine.render("${HOST}:${PORT} $${X}", &env).unwrap(),
+            "example.com:8080 ${X}"
+        );
+        let err = engine.render("${HOST}:${PORT}/${X}", &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: template exceeds the limit of 2 placeholders"
+        );
+    }
+
+    #[test]
+    fn value_validation() {
+        let mut env = context();
+        env.insert("JSON".to_string(), "{}".to_string());
+
+        Substitutor::new().render("${JSON}", &env).unwrap();
+        let engine = Substitutor::new().validate_values(true);
+        let err = engine.render("${JSON}", &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: value of variable 'JSON' contains forbidden character '{'"
+        );
+    }
+
+    #[test]
+    fn recursive_values() {
+        let mut env = context();
+        env.insert("URL".to_string(), "http://${ADDR}/".to_string());
+        env.insert("ADDR".to_string(), "${HOST}:${PORT}".to_string());
+        env.insert("LITERAL".to_string(), "$${HOST} ${X:-${}".to_string());
+
+        let template = "${URL} ${LITERAL}";
+        let out = Substitutor::new().render(template, &env).unwrap();
+        assert_eq!(out, "http://${ADDR}/ $${HOST} ${X:-${}");
+        let out = Substitutor::new()
+            .recursive(2)
+            .render(template, &env)
+            .unwrap();
+        assert_eq!(out, "http://example.com:8080/ ${HOST} ${X:-${}");
+
+        let err = Substitutor::new()
+            .recursive(1)
+            .render(template, &env)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: expansion of variable 'ADDR' exceeds the maximum depth of 1"
+        );
+
+        env.insert("PORT".to_string(), "${URL}".to_string());
+        let err = Substitutor::new()
+            .recursive(10)
+            .render(template, &env)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: variables reference each other in a cycle: URL -> ADDR -> PORT -> URL"
+        );
+
+        env.insert("PORT".to_string(), "${MISSING}".to_string());
+        let engine = Substitutor::new().recursive(10).missing(Missing::Error);
+        match engine.render("\n${URL}", &env).unwrap_err() {
+            Error::MissingVariable { name, position, .. } => {
+                assert_eq!(name, "MISSING");
+                assert_eq!((position.line, position.column), (2, 1));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn unresolved_callback() {
+        let unresolved = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = unresolved.clone();
+        let mut env = context();
+        env.insert("URL".to_string(), "${SCHEME}://${HOST}".to_string());
+
+        let engine = Substitutor::new()
+            .missing(Missing::Empty)
+            .recursive(2)
+            .on_unresolved(move |name, span| {
+                log.lock().unwrap().push((name.to_string(), span));
+            });
+        let out = engine.render("${URL} ${HOST} ${X:-x} ${Y}", &env).unwrap();
+        assert_eq!(out, "://example.com example.com x ");
+        assert_eq!(
+            *unresolved.lock().unwrap(),
+            vec![("SCHEME".to_string(), 0..6), ("Y".to_string(), 23..27)]
+        );
+
+        let template = engine.compile("-${Y}").unwrap();
+        template.render(&env).unwrap();
+        assert_eq!(unresolved.lock().unwrap()[2], ("Y".to_string(), 1..5));
+
+        engine
+            .clone()
+            .missing(Missing::Error)
+            .render("${Y}", &env)
+            .unwrap_err();
+        assert_eq!(unresolved.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn optional_values() {
+        let mut env = HashMap::new();
+        env.insert("HOST".to_string(), Some("example.com".to_string()));
+        env.insert("PORT".to_string(), None);
+        env.insert("PATH".to_string(), Some(String::new()));
+
+        let template = "${HOST}:${PORT:-80}/${PATH:-index} ${PORT}";
+        let out = Substitutor::new().render_optional(template, &env).unwrap();
+        assert_eq!(out, "example.com:80/ ${PORT}");
+
+        let engine = Substitutor::new().missing(Missing::Error);
+        let err = engine.render_optional("${PORT}", &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "envsubst error: variable 'PORT' is not defined (line 1: `${PORT}`)"
+        );
+
+        env.insert("${PORT}".to_string(), None);
+        engine.render_optional("${HOST}", &env).unwrap_err();
+    }
+
+    #[test]
+    fn long_names() {
+        let long = "N".repeat(10_000);
+        let mut env = HashMap::new();
+        env.insert(long.clone(), "value".to_string());
+        let template = format!("ok\n${{{}}}", long);
+
+        let engine = Substitutor::new().max_name_len(16);
+        assert_eq!(engine.render("${HOST}", &env).unwrap(), "${HOST}");
+        for err in [
+            engine.render(&template, &env).unwrap_err(),
+            engine.compile(&template).unwrap_err(),
+        ] {
+            match err {
+                Error::NameTooLong {
+                    name,
+                    len,
+                    limit,
+                    position,
+                } => {
+                    assert_eq!(name, format!("{}...", "N".repeat(32)));
+                    assert_eq!((len, limit), (10_000, 16));
+                    assert_eq!((position.offset, position.line, position.column), (3, 2, 1));
+                }
+                err => panic!("unexpected error: {}", err),
+            }
+        }
+        let engine = engine.max_name_len(10_000);
+        assert_eq!(engine.render(&template, &env).unwrap(), "ok\nvalue");
+    }
+}
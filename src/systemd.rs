@@ -0,0 +1,267 @@
+//! Helpers for systemd-flavored inputs.
+//!
+//! This module provides support for the file formats and conventions of
+//! systemd units, so that tooling built on this crate can consume the same
+//! inputs that systemd itself does.
+
+use crate::{is_identifier, Error, Position};
+use std::collections::HashMap;
+
+/// Characters which can be backslash-escaped within double quotes.
+const SHELL_NEED_ESCAPE: &[char] = &['"', '\\', '`', '$'];
+
+/// Parser states, mirroring systemd's `env-file.c` logic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    PreKey,
+    Key,
+    PreValue,
+    Value,
+    ValueEscape,
+    SingleQuoteValue,
+    DoubleQuoteValue,
+    DoubleQuoteValueEscape,
+    Comment,
+}
+
+/// Parse the content of a systemd `EnvironmentFile=` into variables.
+///
+/// This follows the same rules as systemd: lines starting with `#` or `;`
+/// are comments, values can be single- or double-quoted, a trailing
+/// backslash continues a value on the next line, and unquoted trailing
+/// whitespace is dropped. Assignments with invalid variable names are
+/// ignored, and later assignments override earlier ones.
+pub fn parse_environment_file<S>(input: S) -> HashMap<String, String>
+where
+    S: AsRef<str>,
+{
+    let mut vars = HashMap::new();
+    let mut state = State::PreKey;
+    let mut key = String::new();
+    let mut value = String::new();
+    // Length of `key`/`value` before any trailing unquoted whitespace.
+    let mut key_len = 0;
+    let mut value_len = None;
+
+    for c in input.as_ref().chars() {
+        match state {
+            State::PreKey => {
+                if c == '#' || c == ';' {
+                    state = State::Comment;
+                } else if !c.is_ascii_whitespace() {
+                    state = State::Key;
+                    key.clear();
+                    key.push(c);
+                    key_len = key.len();
+                }
+            }
+            State::Key => {
+                if c == '\n' || c == '\r' {
+                    state = State::PreKey;
+                } else if c == '=' {
+                    state = State::PreValue;
+                    key.truncate(key_len);
+                    value.clear();
+                    value_len = None;
+                } else {
+                    key.push(c);
+                    if !c.is_ascii_whitespace() {
+                        key_len = key.len();
+                    }
+                }
+            }
+            State::PreValue => {
+                if c == '\n' || c == '\r' {
+                    state = State::PreKey;
+                    push_assignment(&mut vars, &key, &value, value_len);
+                } else if c == '\'' {
+                    state = State::SingleQuoteValue;
+                } else if c == '"' {
+                    state = State::DoubleQuoteValue;
+                } else if c == '\\' {
+                    state = State::ValueEscape;
+                } else if !c.is_ascii_whitespace() {
+                    state = State::Value;
+                    value.push(c);
+                    value_len = None;
+                }
+            }
+            State::Value => {
+                if c == '\n' || c == '\r' {
+                    state = State::PreKey;
+                    push_assignment(&mut vars, &key, &value, value_len);
+                } else if c == '\\' {
+                    state = State::ValueEscape;
+                    value_len = None;
+                } else {
+                    if !c.is_ascii_whitespace() {
+                        value_len = None;
+                    } else if value_len.is_none() {
+                        value_len = Some(value.len());
+                    }
+                    value.push(c);
+                }
+            }
+            State::ValueEscape => {
+                state = State::Value;
+                // Escaped newlines are continuation lines, eaten up entirely.
+                if c != '\n' && c != '\r' {
+                    value.push(c);
+                }
+            }
+            State::SingleQuoteValue => {
+                if c == '\'' {
+                    state = State::PreValue;
+                } else {
+                    value.push(c);
+                }
+            }
+            State::DoubleQuoteValue => {
+                if c == '"' {
+                    state = State::PreValue;
+                } else if c == '\\' {
+                    state = State::DoubleQuoteValueEscape;
+                } else {
+                    value.push(c);
+                }
+            }
+            State::DoubleQuoteValueEscape => {
+                state = State::DoubleQuoteValue;
+                if SHELL_NEED_ESCAPE.contains(&c) {
+                    value.push(c);
+                } else if c != '\n' {
+                    // Like the shell, keep the backslash for other characters.
+                    value.push('\\');
+                    value.push(c);
+                }
+            }
+            State::Comment => {
+                if c == '\n' || c == '\r' {
+                    state = State::PreKey;
+                }
+            }
+        }
+    }
+
+    match state {
+        State::PreKey | State::Key | State::Comment => {}
+        _ => push_assignment(&mut vars, &key, &value, value_len),
+    }
+
+    vars
+}
+
+/// Expand systemd unit specifiers in a template string.
+///
+/// Given an input string `template`, replace specifiers of the form `%i`
+/// with the values provided in `specifiers`, keyed by specifier character.
+/// As in unit files, `%%` produces a literal `%`, and specifiers which are
+/// not in the map are an error.
+pub fn expand_specifiers<T>(
+    template: T,
+    specifiers: &HashMap<char, String>,
+) -> Result<String, Error>
+where
+    T: AsRef<str>,
+{
+    let template = template.as_ref();
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some((_, '%')) => output.push('%'),
+            Some((_, specifier)) => match specifiers.get(&specifier) {
+                Some(value) => output.push_str(value),
+                None => {
+                    let position = Position::locate(template, start);
+                    return Err(Error::UnknownSpecifier {
+                        specifier,
+                        position,
+                    });
+                }
+            },
+            // Like systemd, keep a trailing lone '%'.
+            None => output.push('%'),
+        }
+    }
+    Ok(output)
+}
+
+/// Record a parsed assignment, skipping invalid variable names.
+fn push_assignment(
+    vars: &mut HashMap<String, String>,
+    key: &str,
+    value: &str,
+    value_len: Option<usize>,
+) {
+    if !is_identifier(key) {
+        return;
+    }
+    let value = match value_len {
+        Some(len) => &value[..len],
+        None => value,
+    };
+    vars.insert(key.to_string(), value.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_file() {
+        let input = r#"
+# comment
+; another comment
+FOO=bar
+  SPACED = value with spaces
+EMPTY=
+SINGLE='a "quoted" \value'
+DOUBLE="a \"quoted\" \$value \x"
+CONT=first \
+second
+MULTI="line one
+line two"
+JOINED="a"'b' c
+ESCAPED=a\ b\\c
+1INVALID=skipped
+NOEQUALS
+FOO=override
+LAST=eof"#;
+        let vars = parse_environment_file(input);
+
+        let expected = [
+            ("FOO", "override"),
+            ("SPACED", "value with spaces"),
+            ("EMPTY", ""),
+            ("SINGLE", r#"a "quoted" \value"#),
+            ("DOUBLE", r#"a "quoted" $value \x"#),
+            ("CONT", "first second"),
+            ("MULTI", "line one\nline two"),
+            ("JOINED", "abc"),
+            ("ESCAPED", r"a b\c"),
+            ("LAST", "eof"),
+        ];
+        assert_eq!(vars.len(), expected.len());
+        for (k, v) in &expected {
+            assert_eq!(vars[*k], *v, "key {}", k);
+        }
+    }
+
+    #[test]
+    fn specifiers() {
+        let mut specifiers = HashMap::new();
+        specifiers.insert('i', "eth0".to_string());
+        specifiers.insert('n', "dhcp@eth0.service".to_string());
+
+        let out = expand_specifiers("%n: %i at 100%% %", &specifiers).unwrap();
+        assert_eq!(out, "dhcp@eth0.service: eth0 at 100% %");
+
+        let err = expand_specifiers("host %H", &specifiers).unwrap_err();
+        assert_eq!(err.to_string(), "envsubst error: unknown specifier '%H'");
+    }
+}
@@ -0,0 +1,571 @@
+//! Pre-parsed templates.
+
+use crate::placeholder::{Scanner, Token};
+use crate::plan::{RenderPlan, Source};
+use crate::preview::{Preview, Region};
+use crate::substitutor::{missing_error, required_error, Lookup, Resolved};
+use crate::{validate_value, Error, Missing, Position, Substitutor, VariableResolver};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// A template parsed once, for repeated rendering.
+///
+/// Parsing splits the template into literal text and placeholders, so that
+/// rendering only has to look up variables and concatenate segments, without
+/// scanning the template again.
+///
+/// ```rust
+/// use envsubst::Template;
+///
+/// let template = Template::parse("https://${host}/${path:-index.html}").unwrap();
+/// assert_eq!(template.variables().collect::<Vec<_>>(), vec!["host", "path"]);
+///
+/// let mut context = std::collections::HashMap::new();
+/// context.insert("host".to_string(), "example.com".to_string());
+/// assert_eq!(template.render(&context).unwrap(), "https://example.com/index.html");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+    substitutor: Substitutor,
+}
+
+/// A parsed template segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    /// Literal text, with escape sequences already resolved.
+    Literal(String),
+    /// A placeholder, with its original text for `Missing::Keep`.
+    Placeholder {
+        name: String,
+        filters: Vec<String>,
+        default: Option<String>,
+        required: Option<String>,
+        raw: String,
+        position: Position,
+    },
+}
+
+impl Template {
+    /// Parse a template with the default [`Substitutor`] settings.
+    pub fn parse<T>(template: T) -> Result<Self, Error>
+    where
+        T: AsRef<str>,
+    {
+        Substitutor::new().compile(template)
+    }
+
+    /// Parse a template with the settings of `substitutor`.
+    pub(crate) fn compile(substitutor: &Substitutor, template: &str) -> Result<Self, Error> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut last = 0;
+        let mut count = 0;
+        // Position at `last`, to locate placeholders.
+        let mut position = Position::default();
+        for token in Scanner::new(template, substitutor.syntax, substitutor.escape) {
+            let span = token.span();
+            literal.push_str(&template[last..span.start]);
+            position = position.advance(&template[last..span.start]);
+            let start = position;
+            position = position.advance(&template[span.clone()]);
+            last = span.end;
+            match token {
+                Token::Escape { literal: text, .. } => literal.push_str(text),
+                Token::Placeholder(placeholder) => {
+                    count += 1;
+                    if let Some(limit) = substitutor.max_placeholders.filter(|l| count > *l) {
+                        let position = start;
+                        return Err(Error::TooManyPlaceholders { limit, position });
+                    }
+                    let (name, filters) = substitutor.split_filters(placeholder.name());
+                    substitutor
+                        .check_name_len(name)
+                        .map_err(|e| e.shifted(&start))?;
+                    let known = substitutor.filters.as_ref();
+                    if let Some(unknown) = filters
+                        .iter()
+                        .find(|f| !known.map_or(false, |known| known.contains_key(**f)))
+                    {
+                        let filter = unknown.to_string();
+                        let position = start;
+                        return Err(Error::UnknownFilter { filter, position });
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder {
+                        name: name.to_string(),
+                        filters: filters.into_iter().map(str::to_string).collect(),
+                        default: placeholder.default().map(str::to_string),
+                        required: placeholder.required().map(str::to_string),
+                        raw: template[span].to_string(),
+                        position: start,
+                    });
+                }
+            }
+        }
+        literal.push_str(&template[last..]);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            source: template.to_string(),
+            segments,
+            substitutor: substitutor.clone(),
+        })
+    }
+
+    /// Names of the variables referenced by this template.
+    ///
+    /// Names are yielded once each, in order of first appearance.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        let mut seen = std::collections::HashSet::new();
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Placeholder { name, .. } => Some(name.as_str()),
+                Segment::Literal(_) => None,
+            })
+            .filter(move |name| seen.insert(*name))
+    }
+
+    /// Restrict `variables` to the entries referenced by this template.
+    ///
+    /// Rendering with the returned map gives the same result as with the
+    /// full `variables`, so that only the minimal context can be exposed to
+    /// the rendering code. In recursive mode, variables referenced by the
+    /// values of included variables are included as well.
+    ///
+    /// ```rust
+    /// use envsubst::Template;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut context = HashMap::new();
+    /// context.insert("host".to_string(), "example.com".to_string());
+    /// context.insert("password".to_string(), "secret".to_string());
+    ///
+    /// let template = Template::parse("https://${host}/").unwrap();
+    /// let allowed = template.allowed_context(&context);
+    /// assert_eq!(allowed.len(), 1);
+    /// assert_eq!(template.render(&allowed).unwrap(), "https://example.com/");
+    /// ```
+    pub fn allowed_context(&self, variables: &HashMap<String, String>) -> HashMap<String, String> {
+        let names = self.variables().map(str::to_string).collect();
+        allowed_entries(&self.substitutor, names, variables)
+    }
+
+    /// Generate a POSIX shell script exporting the `variables` referenced
+    /// by this template, see [`env::shell_exports`](crate::env::shell_exports).
+    ///
+    /// ```rust
+    /// let mut vars = std::collections::HashMap::new();
+    /// vars.insert("HOST".to_string(), "example.com".to_string());
+    /// vars.insert("UNUSED".to_string(), "x".to_string());
+    ///
+    /// let template = envsubst::Template::parse("https://${HOST}/").unwrap();
+    /// assert_eq!(template.shell_exports(&vars).unwrap(), "export HOST='example.com'\n");
+    /// ```
+    pub fn shell_exports(&self, variables: &HashMap<String, String>) -> Result<String, Error> {
+        crate::env::shell_exports(&self.allowed_context(variables))
+    }
+
+    /// Preview how this template renders with `variables`.
+    ///
+    /// Undefined variables are reported in the preview instead of failing.
+    /// Values are shown before filters and recursive expansion.
+    pub fn preview(&self, variables: &HashMap<String, String>) -> Preview {
+        let regions = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => Region::Literal(text.clone()),
+                Segment::Placeholder {
+                    name,
+                    default,
+                    raw,
+                    position,
+                    ..
+                } => {
+                    let name = name.clone();
+                    let span = position.offset..position.offset + raw.len();
+                    match (self.substitutor.lookup(variables, &name), default) {
+                        (Some(value), _) => Region::Resolved {
+                            name,
+                            value: value.into_owned(),
+                            span,
+                        },
+                        (None, Some(default)) => Region::Defaulted {
+                            name,
+                            value: default.clone(),
+                            span,
+                        },
+                        (None, None) => Region::Unresolved {
+                            name,
+                            raw: raw.clone(),
+                            span,
+                        },
+                    }
+                }
+            })
+            .collect();
+        Preview { regions }
+    }
+
+    /// Substitute variables, with the same results as [`Substitutor::render`].
+    pub fn render(&self, variables: &HashMap<String, String>) -> Result<String, Error> {
+        self.render_lookup(variables)
+    }
+
+    /// Substitute variables which may be explicitly unset, with the same
+    /// results as [`Substitutor::render_optional`].
+    pub fn render_optional(
+        &self,
+        variables: &HashMap<String, Option<String>>,
+    ) -> Result<String, Error> {
+        self.render_lookup(variables)
+    }
+
+    /// Substitute variables resolved on demand, with the same results as
+    /// [`Substitutor::render_resolver`].
+    pub fn render_resolver<R>(&self, resolver: &R) -> Result<String, Error>
+    where
+        R: VariableResolver + ?Sized,
+    {
+        self.render_lookup(&Resolved(resolver))
+    }
+
+    /// Plan the rendering with `variables`, without producing the output.
+    ///
+    /// This resolves every placeholder and does all checks, so that
+    /// [`RenderPlan::commit`] cannot fail anymore. The plan also tells how
+    /// each placeholder is resolved, e.g. for review before the output is
+    /// generated.
+    ///
+    /// ```rust
+    /// use envsubst::plan::Source;
+    ///
+    /// let mut context = std::collections::HashMap::new();
+    /// context.insert("host".to_string(), "example.com".to_string());
+    ///
+    /// let template = envsubst::Template::parse("${host}:${port:-80}").unwrap();
+    /// let plan = template.plan(&context).unwrap();
+    /// let sources: Vec<_> = plan.substitutions().iter().map(|s| s.source).collect();
+    /// assert_eq!(sources, vec![Source::Variable, Source::Default]);
+    /// assert_eq!(plan.commit(), "example.com:80");
+    /// ```
+    pub fn plan(&self, variables: &HashMap<String, String>) -> Result<RenderPlan, Error> {
+        let mut plan = RenderPlan::default();
+        self.resolve_lookup(variables, |value, placeholder| {
+            plan.push(value, placeholder)
+        })?;
+        Ok(plan)
+    }
+
+    fn render_lookup<V>(&self, variables: &V) -> Result<String, Error>
+    where
+        V: Lookup + ?Sized,
+    {
+        let mut output = String::new();
+        self.resolve_lookup(variables, |value, _| output.push_str(value))?;
+        Ok(output)
+    }
+
+    /// Resolve all segments, calling `emit` with each piece of output, and
+    /// the name, span and source of placeholders.
+    fn resolve_lookup<V, F>(&self, variables: &V, mut emit: F) -> Result<(), Error>
+    where
+        V: Lookup + ?Sized,
+        F: FnMut(&str, Option<(&str, Range<usize>, Source)>),
+    {
+        self.substitutor.check_variables(variables)?;
+
+        for segment in &self.segments {
+            let resolved: Option<Cow<'_, str>>;
+            let expanded: String;
+            let filtered: String;
+            let (value, source) = match segment {
+                Segment::Literal(text) => {
+                    emit(text, None);
+                    continue;
+                }
+                Segment::Placeholder {
+                    name,
+                    filters,
+                    default,
+                    required,
+                    raw,
+                    position,
+                } => {
+                    let span = position.offset..position.offset + raw.len();
+                    resolved = self.substitutor.lookup(variables, name);
+                    let found = resolved.as_deref();
+                    if let Some(value) = found.filter(|_| self.substitutor.validate_values) {
+                        validate_value(name, value)?;
+                    }
+                    if let Some(message) = required {
+                        if found.map_or(true, str::is_empty) {
+                            let offset = position.offset;
+                            return Err(required_error(name, message, &self.source, offset));
+                        }
+                    }
+                    let (value, source): (&str, _) = match (found, default) {
+                        (Some(value), _) if self.substitutor.max_depth.is_some() => {
+                            expanded = self
+                                .substitutor
+                                .expand_value(name, value, variables, &mut Vec::new(), span.clone())
+                                .map_err(|e| e.at(&self.source, position.offset))?;
+                            (&expanded, Source::Variable)
+                        }
+                        (Some(value), _) => (value, Source::Variable),
+                        (None, Some(default)) => (default, Source::Default),
+                        (None, None) => {
+                            self.substitutor.report_unresolved(name, None, &span);
+                            match self.substitutor.missing {
+                                Missing::Keep => {
+                                    emit(raw, Some((name, span, Source::Unresolved)));
+                                    continue;
+                                }
+                                Missing::Empty => ("", Source::Unresolved),
+                                Missing::Error => {
+                                    let offset = position.offset;
+                                    return Err(missing_error(
+                                        name,
+                                        &self.source,
+                                        offset,
+                                        variables,
+                                    ));
+                                }
+                            }
+                        }
+                    };
+                    let filters: Vec<&str> = filters.iter().map(String::as_str).collect();
+                    let value = match self.substitutor.apply_filters(&filters, value) {
+                        Ok(Some(value)) => {
+                            filtered = value;
+                            &filtered
+                        }
+                        Ok(None) => value,
+                        Err(e) => return Err(e.at(&self.source, position.offset)),
+                    };
+                    (value, Some((name.as_str(), span, source)))
+                }
+            };
+            emit(value, source);
+        }
+        Ok(())
+    }
+}
+
+/// Restrict `variables` to the entries needed to render placeholders for
+/// `names` with `substitutor`, including nested ones in recursive mode.
+///
+/// Names are resolved like when rendering, falling back to the defaults of
+/// `substitutor`, so that variables nested in defaults are included too.
+pub(crate) fn allowed_entries(
+    substitutor: &Substitutor,
+    mut pending: Vec<String>,
+    variables: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut allowed = HashMap::new();
+    let mut visited = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if visited.contains(&name) {
+            continue;
+        }
+        let value = match substitutor.lookup(variables, &name) {
+            Some(value) => value,
+            None => continue,
+        };
+        if substitutor.max_depth.is_some() {
+            pending.extend(referenced_names(substitutor, &value));
+        }
+        if let Some(value) = variables.get(&name) {
+            allowed.insert(name.clone(), value.clone());
+        }
+        visited.insert(name);
+    }
+    allowed
+}
+
+/// Names of the variables referenced by placeholders in `input`.
+pub(crate) fn referenced_names(substitutor: &Substitutor, input: &str) -> Vec<String> {
+    Scanner::new(input, substitutor.syntax, substitutor.escape)
+        .filter_map(|token| match token {
+            Token::Placeholder(p) => Some(substitutor.split_filters(p.name()).0.to_string()),
+            Token::Escape { .. } => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Syntax;
+
+    #[test]
+    fn parsed_segments() {
+        let template = Template::parse("a$${b}c${d}${e:-f}").unwrap();
+        assert_eq!(
+            template.segments,
+            vec![
+                Segment::Literal("a${b}c".to_string()),
+                Segment::Placeholder {
+                    name: "d".to_string(),
+                    filters: vec![],
+                    default: None,
+                    required: None,
+                    raw: "${d}".to_string(),
+                    position: Position::locate("a$${b}c${d}", 7),
+                },
+                Segment::Placeholder {
+                    name: "e".to_string(),
+                    filters: vec![],
+                    default: Some("f".to_string()),
+                    required: None,
+                    raw: "${e:-f}".to_string(),
+                    position: Position::locate("a$${b}c${d}", 11),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn same_as_substitutor() {
+        let mut env = HashMap::new();
+        env.insert("HOST".to_string(), "example.com".to_string());
+        env.insert("PORT".to_string(), "${HOST}".to_string());
+        env.insert("SELF".to_string(), "${SELF}".to_string());
+
+        let templates = [
+            "",
+            "plain",
+            "${HOST}:${PORT}/${PATH} $${HOST}",
+            "{{ HOST }} $HOST ${X:-y}",
+            "${SELF}",
+            "${HOST:?} ${X:?x is not set}",
+            "${HOST|upper} ${X | trim:-  y  } ${X|upper}",
+        ];
+        let engines = [
+            Substitutor::new(),
+            Substitutor::new().missing(Missing::Empty),
+            Substitutor::new().missing(Missing::Error),
+            Substitutor::new().syntax(Syntax::Dollar),
+            Substitutor::new().syntax(Syntax::DoubleBraces),
+            Substitutor::new().recursive(0),
+            Substitutor::new().recursive(2),
+            Substitutor::new().filters(true),
+            Substitutor::new().filters(true).recursive(2),
+            Substitutor::new().defaults(env.clone()).recursive(2),
+        ];
+        for engine in &engines {
+            for input in &templates {
+                let expected = engine.render(input, &env).map_err(|e| e.to_string());
+                let template = engine.compile(input).unwrap();
+                let out = template.render(&env).map_err(|e| e.to_string());
+                assert_eq!(out, expected, "{:?} with {:?}", input, engine);
+                let planned = template.plan(&env).map(RenderPlan::commit);
+                assert_eq!(planned.map_err(|e| e.to_string()), expected);
+
+                let expected = engine
+                    .render_resolver(input, &env)
+                    .map_err(|e| e.to_string());
+                let out = template.render_resolver(&env).map_err(|e| e.to_string());
+                assert_eq!(out, expected, "{:?} with {:?}", input, engine);
+            }
+        }
+    }
+
+    #[test]
+    fn variables_list() {
+        let template = Template::parse("${b}${a}${b:-x} $${c}").unwrap();
+        assert_eq!(template.variables().collect::<Vec<_>>(), vec!["b", "a"]);
+
+        let engine = Substitutor::new().max_placeholders(2);
+        engine.compile("${b}${a}${b:-x}").unwrap_err();
+
+        let engine = Substitutor::new().filters(true);
+        let template = engine.compile("${a|upper} ${b | lower|trim} ${a}").unwrap();
+        assert_eq!(template.variables().collect::<Vec<_>>(), vec!["a", "b"]);
+        match engine.compile("${a}\n${b|rot13}").unwrap_err() {
+            Error::UnknownFilter { filter, position } => {
+                assert_eq!((filter.as_str(), position.line), ("rot13", 2));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn planned_render() {
+        let mut env = HashMap::new();
+        env.insert("HOST".to_string(), "Example.com".to_string());
+        let engine = Substitutor::new().filters(true);
+
+        let plan = engine
+            .plan("$${X}${HOST|lower}:${PORT:-80} ${X}", &env)
+            .unwrap();
+        let found: Vec<_> = plan
+            .substitutions()
+            .iter()
+            .map(|s| (s.name.as_str(), s.span.clone(), s.value.as_str(), s.source))
+            .collect();
+        let expected = vec![
+            ("HOST", 5..18, "example.com", Source::Variable),
+            ("PORT", 19..30, "80", Source::Default),
+            ("X", 31..35, "${X}", Source::Unresolved),
+        ];
+        assert_eq!(found, expected);
+        assert_eq!(plan.commit(), "${X}example.com:80 ${X}");
+
+        let engine = engine.missing(Missing::Error);
+        engine.plan("${HOST} ${X}", &env).unwrap_err();
+    }
+
+    #[test]
+    fn precompiled() {
+        let engine = Substitutor::new().filters(true);
+        let sources = vec!["${a|upper}".to_string(), "${b|nope}".to_string()];
+        let errors = engine.precompile(&sources).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], (1, Error::UnknownFilter { .. })));
+
+        let templates = engine.precompile(&sources[..1]).unwrap();
+        let mut env = HashMap::new();
+        env.insert("a".to_string(), "x".to_string());
+        assert_eq!(templates[0].render(&env).unwrap(), "X");
+        assert!(engine.precompile(Vec::<String>::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn allowed_entries() {
+        let mut env = HashMap::new();
+        env.insert("HOST".to_string(), "example.com".to_string());
+        env.insert("URL".to_string(), "https://${HOST}".to_string());
+        env.insert("SECRET".to_string(), "hunter2".to_string());
+        let names = |context: HashMap<String, String>| {
+            let mut names: Vec<_> = context.into_keys().collect();
+            names.sort_unstable();
+            names
+        };
+
+        let template = Template::parse("${URL} ${MISSING} $${SECRET}").unwrap();
+        assert_eq!(names(template.allowed_context(&env)), vec!["URL"]);
+
+        let template = Substitutor::new().recursive(4).compile("${URL}").unwrap();
+        let allowed = template.allowed_context(&env);
+        assert_eq!(template.render(&allowed).unwrap(), "https://example.com");
+        assert_eq!(names(allowed), vec!["HOST", "URL"]);
+
+        let mut defaults = HashMap::new();
+        defaults.insert("URL".to_string(), "https://${HOST}${PATH}".to_string());
+        defaults.insert("PATH".to_string(), "/${PATH}".to_string());
+        env.remove("URL");
+        let engine = Substitutor::new().defaults(defaults).recursive(4);
+        let template = engine.compile("${URL}").unwrap();
+        let allowed = template.allowed_context(&env);
+        assert_eq!(names(allowed), vec!["HOST"]);
+    }
+}